@@ -1,20 +1,22 @@
 use iced::widget::{button, column, container, text};
 use iced::window::Event as WindowEvent;
 use iced::{Element, Event, Subscription, Task};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tray_icon::TrayIconEvent;
 
 use crate::config::Config;
-use crate::gamepad::GamepadHandler;
 use crate::menu::SetupComponents;
 use crate::setupapp::setup;
 use crate::tray::{hide_window, show_window, tray_event_subscription};
+use crate::worker::{GamepadWorker, WorkerStatus};
+
+/// The gamepad runs on its own thread, the GUI only needs to refresh its status now and then.
+const STATUS_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
 
 pub struct StickApp {
-    gamepad_handler: Option<GamepadHandler>,
+    worker: GamepadWorker,
     _tray_components: SetupComponents,
-    last_update: Instant,
-    target_interval: Duration,
+    worker_status: WorkerStatus,
     status_message: String,
     is_visible: bool,
 }
@@ -22,6 +24,7 @@ pub struct StickApp {
 #[derive(Debug, Clone)]
 pub enum Message {
     Tick,
+    ReloadConfig,
     Quit,
     WindowEvent(Event),
     TrayEvent(TrayIconEvent),
@@ -32,17 +35,13 @@ pub enum Message {
 impl StickApp {
     pub fn new(config: Config) -> Result<(Self, Task<Message>), Box<dyn std::error::Error>> {
         let tray_components = setup()?;
-        let gamepad_handler = GamepadHandler::new(config)?;
-
-        let frequency = gamepad_handler.config.frequency;
-        let target_interval = Duration::from_secs_f32(1.0 / frequency);
+        let worker = GamepadWorker::spawn(config)?;
 
         Ok((
             Self {
-                gamepad_handler: Some(gamepad_handler),
+                worker,
                 _tray_components: tray_components,
-                last_update: Instant::now(),
-                target_interval,
+                worker_status: WorkerStatus::default(),
                 status_message: String::from("TheStickening is running"),
                 is_visible: true,
             },
@@ -57,12 +56,18 @@ impl StickApp {
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::Tick => {
-                if let Some(handler) = &mut self.gamepad_handler
-                    && let Err(e) = handler.process_frame()
-                {
-                    eprintln!("Error processing gamepad: {}", e);
+                if let Some(status) = self.worker.latest_status() {
+                    self.worker_status = status;
                 }
-                self.last_update = Instant::now();
+                Task::none()
+            }
+            Message::ReloadConfig => {
+                let reloaded = Config::load_from_file(None)
+                    .and_then(|config| self.worker.update_config(config));
+                self.status_message = match reloaded {
+                    Ok(()) => String::from("Configuration reloaded"),
+                    Err(e) => format!("Could not reload the configuration: {}", e),
+                };
                 Task::none()
             }
             Message::Quit => iced::exit(),
@@ -97,10 +102,22 @@ impl StickApp {
             "Running in tray - click tray icon to show"
         };
 
+        let worker = &self.worker_status;
+        let timing = format!(
+            "{:.0} Hz, jitter {:.2} ms average, {:.2} ms worst",
+            worker.tick_rate,
+            worker.mean_jitter.as_secs_f32() * 1000.,
+            worker.max_jitter.as_secs_f32() * 1000.,
+        );
+
         let content = column![
             text("TheStickening").size(24),
             text(&self.status_message).size(14),
             text(status).size(12),
+            text(timing).size(12),
+            text(&worker.output).size(12),
+            text(worker.last_error.as_deref().unwrap_or("")).size(12),
+            button("Reload configuration").on_press(Message::ReloadConfig),
             button("Quit").on_press(Message::Quit),
         ]
         .spacing(10)
@@ -111,7 +128,7 @@ impl StickApp {
 
     pub fn subscription(&self) -> Subscription<Message> {
         Subscription::batch(vec![
-            iced::time::every(self.target_interval).map(|_| Message::Tick),
+            iced::time::every(STATUS_REFRESH_INTERVAL).map(|_| Message::Tick),
             tray_event_subscription().map(Message::TrayEvent),
             iced::event::listen().map(Message::WindowEvent),
         ])
@@ -1,48 +1,325 @@
 use gilrs::{Axis, Button};
+use serde::de::IntoDeserializer;
+use serde::de::value::StrDeserializer;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::keys::{Key, KeyCombo};
+
+/// Highest `frequency` we accept, past that the gamepad does not report anything new anyway.
+pub const MAX_FREQUENCY: f32 = 1000.;
 
 /// Configuration struct for gamepad to mouse mapping
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
-    /// Mapping between gamepad buttons and their corresponding actions
-    pub button_mapping: HashMap<String, ButtonAction>,
-    // button_mapping is not int32 because not valid toml key type.
+    /// Mapping between gamepad buttons and their corresponding actions.
+    /// Keyed by gilrs button name ("South", "LeftTrigger", "DPadUp"), so the same file works
+    /// across controllers. The raw event code ("304") still works for buttons gilrs does not
+    /// recognise, the name wins when both are mapped.
+    pub button_mapping: HashMap<String, ButtonBinding>,
+    // Codes are strings too because integers are not valid toml keys.
     /// Which joystick to use for mouse movement (left or right)
     pub mouse_joystick: Joystick,
-    /// Default sensitivity for mouse movement
-    pub mouse_sensitivity: f32,
-    /// Button mapped for accurate aiming mode (default: left trigger)
-    pub aim_button: Button,
-    /// Sensitivity decrease factor when aiming. Ratio between 0 and 1. (or more if you want it to
-    /// get faster somehow). The total sensitivity will be mutiplied at maximum at that value.
-    /// For instance a trigger fully pressed with aim_sensitivity_factor = 0.5 means we will divide
-    /// by two the speed of the cursor.
-    /// Just make sure you have mouse_sensitivity / aim_sensitivity_factor >= 1 because it has to
-    /// end up to pixels.
-    pub aim_sensitivity_factor: f32,
-    /// Joystick deadzone for detecting movement
+    /// Cursor speed, in pixels per second with the stick fully pushed.
+    /// It does not depend on `frequency`.
+    #[serde(default = "default_mouse_speed")]
+    pub mouse_speed: f32,
+    /// Replaced by `mouse_speed`, this one was in pixels per frame. Older configuration files
+    /// setting it get it multiplied by `frequency`.
+    #[serde(default)]
+    pub mouse_sensitivity: Option<f32>,
+    /// Lets the other stick move the cursor as well, at this many pixels per second, for fine
+    /// adjustments while `mouse_joystick` does the travel. Both movements add up, each stick
+    /// keeps its own smoothing and settings, and the modifiers only speed up the main one.
+    /// Off when missing, and `scroll.source` must be another stick.
+    #[serde(default)]
+    pub fine_joystick_sensitivity: Option<f32>,
+    /// Speeds the cursor up when the stick stays pushed for a while, off when missing.
+    #[serde(default)]
+    pub acceleration_ramp: Option<AccelerationRamp>,
+    /// Buttons or axes changing the cursor speed while held or once toggled, like a precise
+    /// aiming mode on the left trigger.
+    #[serde(default)]
+    pub modifiers: Vec<SensitivityModifier>,
+    /// Replaced by `modifiers`, older configuration files setting these get them turned into a
+    /// modifier using `zaxis_smoothing` as its curve.
+    #[serde(default)]
+    pub aim_button: Option<Button>,
+    #[serde(default)]
+    pub aim_sensitivity_factor: Option<f32>,
+    /// Joystick deadzone for detecting movement, how it is measured depends on each stick
+    /// `deadzone_shape`.
     pub joystick_deadzone: f32,
+    #[serde(default)]
+    pub left_joystick: StickSettings,
+    #[serde(default)]
+    pub right_joystick: StickSettings,
     /// Axis smoothing
     pub zaxis_smoothing: AccelerationProfile,
     pub left_joystick_smoothing: AccelerationProfile,
     pub right_joystick_smoothing: AccelerationProfile,
+    /// Smoothing of the other axes, like the D-pad or the ones gilrs does not know.
+    #[serde(default = "default_axis_smoothing")]
+    pub default_axis_smoothing: AccelerationProfile,
+
+    /// Routes axes to the pointer, the wheel or the cursor speed. Once it has entries it replaces
+    /// `mouse_joystick` and `scroll.source`, the stick axes still go through their stick settings
+    /// and smoothing.
+    #[serde(default)]
+    pub axis_mapping: Vec<AxisMapping>,
 
-    /// Not rendering every frame as we could not slow down enough the mouse.
-    /// Specify it in hertz.
+    /// Actions triggered by pushing an axis past a threshold, for instance a D-pad the driver
+    /// reports as two axes. Keyed by axis name ("DPadX", "LeftZ") or, for the axes gilrs does
+    /// not know, by their raw event code. Axes without an entry are simply ignored.
+    #[serde(default)]
+    pub axis_buttons: HashMap<String, AxisButtons>,
+
+    /// How often we read the gamepad and move the mouse, in hertz, up to 1000.
+    /// Higher is smoother, the speed stays the same.
     pub frequency: f32,
+
+    /// Scroll wheel driven by a stick or the triggers
+    #[serde(default)]
+    pub scroll: ScrollConfig,
+
+    /// Actions triggered by pressing several buttons together, for instance
+    /// `chords = [{ buttons = ["Select", "Start"], action = { Combo = "ctrl+alt+m" } }]`
+    #[serde(default)]
+    pub chords: Vec<Chord>,
+    /// Seconds within which the buttons of a chord must all be pressed. Buttons that are part of
+    /// a chord wait that long before doing their own action, in case the rest of it follows.
+    #[serde(default = "default_chord_window")]
+    pub chord_window: f32,
+
+    /// Alternate mappings switched on by a button, by name.
+    #[serde(default)]
+    pub layers: HashMap<String, Layer>,
+
+    /// Where the generated mouse and keyboard events are sent. Linux only, Windows always uses
+    /// its own API.
+    #[serde(default)]
+    pub output_backend: OutputBackend,
+}
+
+/// Time based acceleration, for crossing big screens without giving up a precise sensitivity.
+/// For instance `acceleration_ramp = { threshold = 0.9, max_multiplier = 3, duration = 1.5 }`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccelerationRamp {
+    /// Stick deflection, after the deadzone and profile, past which the ramp starts.
+    pub threshold: f32,
+    /// Speed multiplier reached at the end of the ramp.
+    pub max_multiplier: f32,
+    /// Seconds the stick must be held past `threshold` to reach `max_multiplier`. Going back
+    /// under the threshold starts over from normal speed.
+    pub duration: f32,
+}
+
+impl AccelerationRamp {
+    /// Multiplier after holding the stick past the threshold for `held`, grows linearly.
+    pub fn multiplier(&self, held: Duration) -> f32 {
+        let progress = (held.as_secs_f32() / self.duration.max(f32::EPSILON)).min(1.0);
+        1.0 + (self.max_multiplier - 1.0) * progress
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // At 0 or below even a centered stick would ramp up.
+        if !(self.threshold > 0.0 && self.threshold <= 1.0) {
+            return Err(format!(
+                "threshold is {}, it must be above 0 and at most 1",
+                self.threshold
+            ));
+        }
+        if !(self.max_multiplier >= 1.0 && self.max_multiplier.is_finite()) {
+            return Err(format!(
+                "max_multiplier must be a number, 1 or more, got {}",
+                self.max_multiplier
+            ));
+        }
+        if !(self.duration >= 0.0 && self.duration.is_finite()) {
+            return Err(format!(
+                "duration must be a number of seconds, 0 or more, got {}",
+                self.duration
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Scales the cursor speed while its input is pressed.
+/// For instance `{ input = { Button = "LeftTrigger2" }, factor = 0.3 }` slows the cursor down
+/// as the left trigger is pressed, down to 30% of the speed once fully pressed.
+/// When several modifiers are active their factors multiply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SensitivityModifier {
+    pub input: ModifierInput,
+    /// Speed multiplier with the input fully pressed. Below 1 slows down, above 1 speeds up.
+    pub factor: f32,
+    /// How a partially pressed analog input gets to `factor`
+    #[serde(default = "default_modifier_curve")]
+    pub curve: AccelerationProfile,
+    #[serde(default)]
+    pub mode: ModifierMode,
+}
+
+fn default_mouse_speed() -> f32 {
+    250.
+}
+
+fn default_modifier_curve() -> AccelerationProfile {
+    AccelerationProfile::Linear
+}
+
+/// What drives a sensitivity modifier. Buttons report an analog value between 0 and 1 on
+/// triggers, axes count how far they are pushed in either direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModifierInput {
+    Button(Button),
+    Axis(Axis),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModifierMode {
+    /// Active while held, following how far analog inputs are pressed
+    #[default]
+    Hold,
+    /// Each press past halfway switches the full factor on or off
+    Toggle,
+}
+
+/// One line of the axis mapping table, for instance "right stick moves, left stick scrolls,
+/// right trigger accelerates":
+/// ```toml
+/// axis_mapping = [
+///     { input = { Axis = "RightStickX" }, target = "PointerX" },
+///     { input = { Axis = "RightStickY" }, target = "PointerY" },
+///     { input = { Axis = "LeftStickY" }, target = "WheelY", deadzone = 0.1 },
+///     { input = { Axis = "RightZ" }, target = "Sensitivity", scale = 2 },
+/// ]
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AxisMapping {
+    pub input: AxisInput,
+    pub target: AxisTarget,
+    /// Multiplies the axis value, negative to invert it.
+    #[serde(default = "default_axis_scale")]
+    pub scale: f32,
+    /// Defaults to the profile of the axis: the stick or z axis smoothing, or
+    /// `default_axis_smoothing`.
+    #[serde(default)]
+    pub curve: Option<AccelerationProfile>,
+    /// Only for the axes other than the sticks, those use `joystick_deadzone` and their
+    /// `deadzone_shape`.
+    #[serde(default)]
+    pub deadzone: f32,
+}
+
+fn default_axis_scale() -> f32 {
+    1.0
+}
+
+/// An axis known to gilrs, or the raw event code of one it does not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AxisInput {
+    Axis(Axis),
+    Code(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AxisTarget {
+    /// Moves the pointer, at `mouse_speed` pixels per second for a value of 1.
+    PointerX,
+    PointerY,
+    /// Scrolls, at `scroll.sensitivity` notches per second for a value of 1.
+    WheelX,
+    WheelY,
+    /// Multiplies the cursor speed by 1 + value, so a scale of 2 triples it once fully pressed
+    /// and a scale of -0.7 slows it down to 30%.
+    Sensitivity,
+}
+
+fn default_axis_smoothing() -> AccelerationProfile {
+    AccelerationProfile::Linear
+}
+
+/// Actions of the two directions of an axis, each pressed while the axis is past `threshold`.
+/// For instance `DPadX = { negative = { Key = "Left" }, positive = { Key = "Right" } }`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AxisButtons {
+    /// Left, or down for vertical axes
+    pub negative: Option<ButtonAction>,
+    /// Right, or up for vertical axes
+    pub positive: Option<ButtonAction>,
+    #[serde(default = "default_axis_button_threshold")]
+    pub threshold: f32,
+}
+
+fn default_axis_button_threshold() -> f32 {
+    0.5
+}
+
+impl AxisButtons {
+    /// Action of the given direction, -1, 0 or 1.
+    pub fn action(&self, direction: i8) -> Option<&ButtonAction> {
+        match direction {
+            d if d < 0 => self.negative.as_ref(),
+            d if d > 0 => self.positive.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Direction the axis is pushed in, 0 when not past the threshold.
+    pub fn direction(&self, value: f32) -> i8 {
+        if value >= self.threshold {
+            1
+        } else if value <= -self.threshold {
+            -1
+        } else {
+            0
+        }
+    }
+}
+
+/// Linux backends able to inject input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputBackend {
+    /// Virtual device created through /dev/uinput, works everywhere but needs write access to it.
+    #[default]
+    Uinput,
+    /// XTest extension of the X server, for X11 sessions where /dev/uinput is locked down.
+    XTest,
 }
 
 /// Represents available joysticks
 /// If you have more than two joystick, I do not support general grievous playing style :(
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Joystick {
     Left,
     Right,
 }
 impl Joystick {
+    /// The stick an axis belongs to, if any.
+    pub fn from_axis(axis: Axis) -> Option<Self> {
+        match axis {
+            Axis::LeftStickX | Axis::LeftStickY => Some(Self::Left),
+            Axis::RightStickX | Axis::RightStickY => Some(Self::Right),
+            _ => None,
+        }
+    }
+    pub fn other(&self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+    pub fn settings<'a>(&self, config: &'a Config) -> &'a StickSettings {
+        match self {
+            Self::Left => &config.left_joystick,
+            Self::Right => &config.right_joystick,
+        }
+    }
     pub fn y_axis(&self) -> Axis {
         match self {
             Self::Left => Axis::LeftStickY,
@@ -57,7 +334,150 @@ impl Joystick {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Settings specific to one stick
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StickSettings {
+    pub deadzone_shape: DeadzoneShape,
+    /// Deflection past which the stick counts as fully pushed. Lower it if a worn stick never
+    /// reaches full speed.
+    pub outer_deadzone: f32,
+    /// How the stick smoothing profile is applied
+    pub curve_mode: CurveMode,
+    /// Smooths the raw stick values over time, against worn sticks that tremble.
+    pub filter: StickFilter,
+    /// Seconds after a sharp release during which the stick going past the center is ignored,
+    /// so the spring overshoot does not jerk the cursor backwards. 0 disables it, 0.05 is a
+    /// good start.
+    pub snapback_window: f32,
+    /// Speed multipliers of each axis on top of `mouse_speed`, for instance a higher
+    /// horizontal one on ultrawide screens.
+    pub x_sensitivity: f32,
+    pub y_sensitivity: f32,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    /// Degrees the stick readings are turned counterclockwise, to straighten a stick that
+    /// reports a straight push as slightly tilted.
+    pub rotation: f32,
+}
+
+impl StickSettings {
+    /// Straightens the stick readings, done before the deadzone so it lines up with the stick.
+    pub fn rotate(&self, vector: [f32; 2]) -> [f32; 2] {
+        if self.rotation == 0.0 {
+            return vector;
+        }
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let [x, y] = vector;
+        [x * cos - y * sin, x * sin + y * cos]
+    }
+
+    /// Applies the per axis sensitivity and inversion.
+    pub fn scale(&self, vector: [f32; 2]) -> [f32; 2] {
+        let sign = |inverted: bool| if inverted { -1.0 } else { 1.0 };
+        [
+            vector[0] * self.x_sensitivity * sign(self.invert_x),
+            vector[1] * self.y_sensitivity * sign(self.invert_y),
+        ]
+    }
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        Self {
+            deadzone_shape: DeadzoneShape::ScaledRadial,
+            outer_deadzone: 1.0,
+            curve_mode: CurveMode::PerAxis,
+            filter: StickFilter::None,
+            snapback_window: 0.0,
+            x_sensitivity: 1.0,
+            y_sensitivity: 1.0,
+            invert_x: false,
+            invert_y: false,
+            rotation: 0.0,
+        }
+    }
+}
+
+/// How the deadzone is measured on the 2D stick position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeadzoneShape {
+    /// Each axis on its own. Square deadzone, snaps to horizontal/vertical near the center.
+    Axial,
+    /// On the distance from the center, the output jumps once outside of it.
+    Radial,
+    /// Radial, rescaled so the output grows from zero at the deadzone edge.
+    ScaledRadial,
+    /// Scaled radial, then a per axis deadzone growing with the other axis on the result. Keeps
+    /// mostly straight pushes straight without the snapping of `Axial`.
+    Hybrid,
+}
+
+/// Filter applied on the raw stick values, before the deadzone
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StickFilter {
+    None,
+    /// Exponential moving average, the output takes `time_constant` seconds to get about two
+    /// thirds of the way to the stick position. Simple but slows down flicks too.
+    /// For instance `filter = { Ema = { time_constant = 0.03 } }`
+    Ema { time_constant: f32 },
+    /// One Euro filter: `min_cutoff` (hertz) sets how much a slow or resting stick is smoothed,
+    /// lower is smoother. `beta` raises the cutoff with the stick speed so fast moves keep up,
+    /// higher means less lag. `derivative_cutoff` smooths the speed estimate, 1 is fine.
+    /// For instance `filter = { OneEuro = { min_cutoff = 1.0, beta = 5.0, derivative_cutoff = 1.0 } }`
+    OneEuro {
+        min_cutoff: f32,
+        beta: f32,
+        derivative_cutoff: f32,
+    },
+}
+
+/// How an `AccelerationProfile` is applied to a stick
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CurveMode {
+    /// On X and Y separately. Non linear profiles bend diagonals: a 45° push comes out at a
+    /// different speed than a straight one and drifts towards the closest axis.
+    PerAxis,
+    /// On the distance from the center, keeping the stick angle untouched.
+    Magnitude,
+}
+
+/// Scroll wheel settings, the stick or triggers go through their own deadzone and profile.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScrollConfig {
+    pub source: ScrollSource,
+    /// Notches scrolled per second when the source is fully pushed. Fractions of a notch add up
+    /// over the frames, so a light push scrolls slowly instead of a full notch every frame.
+    pub sensitivity: f32,
+    pub deadzone: f32,
+    pub smoothing: AccelerationProfile,
+}
+
+/// What drives the scroll wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScrollSource {
+    Disabled,
+    /// Vertical and horizontal scrolling. Pick the stick that does not move the mouse.
+    LeftStick,
+    RightStick,
+    /// Right trigger scrolls up and left trigger scrolls down, vertical only.
+    /// Uses the Z axes, or the trigger buttons when the driver reports triggers as buttons.
+    Triggers,
+}
+
+impl Default for ScrollConfig {
+    // Used for configuration files written before scrolling existed, so off by default.
+    fn default() -> Self {
+        Self {
+            source: ScrollSource::Disabled,
+            sensitivity: 15.,
+            deadzone: 0.1,
+            smoothing: AccelerationProfile::SmootherStep,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AccelerationProfile {
     Linear,
     SmoothStep,       // f(t) = t² (3 − 2t)
@@ -72,6 +492,140 @@ pub enum AccelerationProfile {
                       // t == 1 ? 1 :
                       // t < 0.5 ? 2^(20t − 10)/2 :
                       // (2 − 2^(−20t + 10))/2
+    /// f(t) = t^exponent, for instance { Power = 2.5 }
+    Power(f32),
+    /// Your own curve, see CustomCurve
+    Custom(CustomCurve),
+}
+
+/// Curves described in the config file, they must go from (0, 0) to (1, 1) without ever going
+/// down. Points are [input, output] pairs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CustomCurve {
+    /// Points joined by straight lines, from [0, 0] to [1, 1] with increasing inputs.
+    /// For instance { Custom = { PiecewiseLinear = [[0, 0], [0.6, 0.2], [1, 1]] } }
+    PiecewiseLinear(Vec<[f32; 2]>),
+    /// Cubic Bézier from [0, 0] to [1, 1] with the two given control points, each coordinate
+    /// between 0 and 1. Same as CSS cubic-bezier(), for instance
+    /// { Custom = { Bezier = [[0.6, 0.0], [0.8, 0.4]] } }
+    Bezier([f32; 2], [f32; 2]),
+}
+
+/// What a button does: either an action pressed and released along with it, or different
+/// actions depending on how it is pressed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ButtonBinding {
+    Action(ButtonAction),
+    Gestures(ButtonGestures),
+    Repeat(RepeatBinding),
+}
+
+/// An action sent again and again while the button is held, like keyboard key repeat. For
+/// instance `DPadDown = { action = { Key = "Down" }, delay = 0.4, rate = 20 }`, or autofire
+/// with `RightTrigger = { action = "MouseLeft", rate = 15, turbo = true }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepeatBinding {
+    pub action: ButtonAction,
+    /// Seconds the button is held before repeating starts
+    #[serde(default)]
+    pub delay: f32,
+    /// Repeats per second
+    pub rate: f32,
+    /// Alternates the action between pressed and released instead of pressing it again right
+    /// away, so each click lasts long enough for games to notice. Meant for mouse buttons.
+    #[serde(default)]
+    pub turbo: bool,
+}
+
+/// Buttons pressed together, the action is held until one of them is released. The buttons do
+/// not trigger their own mapping when they are part of it. Listing a button under both its name
+/// and its raw code makes a chord that never fires, it would need two presses of that button.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Chord {
+    /// By name or raw code, like in `button_mapping`
+    pub buttons: Vec<String>,
+    pub action: ButtonAction,
+}
+
+fn default_chord_window() -> f32 {
+    0.05
+}
+
+/// Mappings used instead of the base ones while the layer is active. Buttons and axes the layer
+/// does not bind keep their base mapping. For instance
+/// ```toml
+/// [layers.edit]
+/// button = "LeftTrigger"
+/// button_mapping = { South = { Combo = "ctrl+c" }, East = { Combo = "ctrl+v" } }
+/// ```
+/// Whatever is held when the layer changes gets released, so nothing stays stuck down.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Layer {
+    /// Button activating the layer, by name or raw code like in `button_mapping`. It only
+    /// switches the layer, so it cannot be in a `button_mapping`, a chord or another layer.
+    pub button: String,
+    #[serde(default)]
+    pub activation: LayerActivation,
+    #[serde(default)]
+    pub button_mapping: HashMap<String, ButtonBinding>,
+    /// Replaces the whole base `axis_mapping` when not empty.
+    #[serde(default)]
+    pub axis_mapping: Vec<AxisMapping>,
+    #[serde(default)]
+    pub axis_buttons: HashMap<String, AxisButtons>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LayerActivation {
+    /// Active while the button is held
+    #[default]
+    Hold,
+    /// Each press switches the layer on or off
+    Toggle,
+}
+
+impl From<ButtonAction> for ButtonBinding {
+    fn from(action: ButtonAction) -> Self {
+        Self::Action(action)
+    }
+}
+
+/// Actions per gesture, any of them can be left out. For instance
+/// `East = { tap = { Key = "Escape" }, hold = "MouseRight" }`
+/// A tap is only sent once the button is released, or after `double_tap_window` when a double
+/// tap is bound, so bind only what you need to keep it responsive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ButtonGestures {
+    /// Quick press and release, sent as a click
+    pub tap: Option<ButtonAction>,
+    /// Pressed once the button is held for `hold_time`, released with it
+    pub hold: Option<ButtonAction>,
+    /// Second press shortly after a tap, pressed and released with the button
+    pub double_tap: Option<ButtonAction>,
+    /// Sent as a click once the button is held for `long_press_time`, on top of `hold`
+    pub long_press: Option<ButtonAction>,
+    /// In seconds
+    #[serde(default = "default_hold_time")]
+    pub hold_time: f32,
+    #[serde(default = "default_long_press_time")]
+    pub long_press_time: f32,
+    #[serde(default = "default_double_tap_window")]
+    pub double_tap_window: f32,
+}
+
+fn default_hold_time() -> f32 {
+    0.25
+}
+
+fn default_long_press_time() -> f32 {
+    1.0
+}
+
+fn default_double_tap_window() -> f32 {
+    0.25
 }
 
 /// Represents actions that can be triggered by buttons
@@ -80,7 +634,11 @@ pub enum ButtonAction {
     MouseLeft,
     MouseRight,
     MouseMiddle,
-    // Note this does not aim to cover all buttons.
+    /// Presses the given keyboard key, for instance `{ Key = "Escape" }`.
+    Key(Key),
+    /// Presses several keys at once, for instance `{ Combo = "ctrl+shift+t" }`.
+    Combo(KeyCombo),
+    // Same as the matching Key(..), kept so older configuration files still load.
     LeftArrow,
     RightArrow,
     UpArrow,
@@ -90,22 +648,44 @@ pub enum ButtonAction {
 impl Default for Config {
     fn default() -> Self {
         let mut button_mapping = HashMap::new();
-        button_mapping.insert("0".to_string(), ButtonAction::MouseLeft); // A button
-        button_mapping.insert("1".to_string(), ButtonAction::MouseRight); // B button
+        button_mapping.insert("South".to_string(), ButtonAction::MouseLeft.into()); // A button
+        button_mapping.insert("East".to_string(), ButtonAction::MouseRight.into()); // B button
 
         Self {
             button_mapping,
             mouse_joystick: Joystick::Left,
-            mouse_sensitivity: 5.0,
-            aim_button: Button::LeftTrigger2, // Left trigger
-            aim_sensitivity_factor: 3.,       // Decreases up to if <1, increase up to if >1
-            // The min mouse sensitivity is to avoid that we press aim and the mouse stops moving.
+            mouse_speed: default_mouse_speed(),
+            mouse_sensitivity: None,
+            fine_joystick_sensitivity: None,
+            acceleration_ramp: None,
+            // Pressing the left trigger speeds the cursor up, up to 3 times.
+            modifiers: vec![SensitivityModifier {
+                input: ModifierInput::Button(Button::LeftTrigger2),
+                factor: 3.,
+                curve: AccelerationProfile::SmootherStep,
+                mode: ModifierMode::Hold,
+            }],
+            aim_button: None,
+            aim_sensitivity_factor: None,
             // The joystick deadzone is to avoid mouse movement when the joystick is at rest.
             joystick_deadzone: 0.005,
+            left_joystick: StickSettings::default(),
+            right_joystick: StickSettings::default(),
             frequency: 50.,
             zaxis_smoothing: AccelerationProfile::SmootherStep,
             left_joystick_smoothing: AccelerationProfile::SmootherStep,
             right_joystick_smoothing: AccelerationProfile::SmootherStep,
+            default_axis_smoothing: default_axis_smoothing(),
+            axis_mapping: Vec::new(),
+            axis_buttons: HashMap::new(),
+            scroll: ScrollConfig {
+                source: ScrollSource::RightStick,
+                ..ScrollConfig::default()
+            },
+            chords: Vec::new(),
+            chord_window: default_chord_window(),
+            layers: HashMap::new(),
+            output_backend: OutputBackend::default(),
         }
     }
 }
@@ -148,7 +728,396 @@ impl Config {
         }
 
         let content = fs::read_to_string(load_path)?;
-        let config: Config = toml::from_str(&content)?;
+        let mut config: Config = toml::from_str(&content)?;
+        config.upgrade_mouse_sensitivity();
+        config.upgrade_aim_button();
+        config.validate()?;
         Ok(config)
     }
+
+    /// Turns the per frame `mouse_sensitivity` of older configuration files into `mouse_speed`.
+    fn upgrade_mouse_sensitivity(&mut self) {
+        if let Some(sensitivity) = self.mouse_sensitivity.take() {
+            self.mouse_speed = sensitivity * self.frequency;
+        }
+    }
+
+    /// Turns the `aim_button` of older configuration files into the matching modifier.
+    fn upgrade_aim_button(&mut self) {
+        if let Some(button) = self.aim_button.take() {
+            self.modifiers.push(SensitivityModifier {
+                input: ModifierInput::Button(button),
+                factor: self.aim_sensitivity_factor.take().unwrap_or(1.),
+                curve: self.zaxis_smoothing.clone(),
+                mode: ModifierMode::Hold,
+            });
+        }
+    }
+
+    /// Checks what serde cannot, like curves that actually are curves.
+    pub fn validate(&self) -> Result<(), String> {
+        // Also catches NaN, the frame period is computed from it.
+        if !(1.0..=MAX_FREQUENCY).contains(&self.frequency) {
+            return Err(format!(
+                "frequency is {}, it must be between 1 and {} hertz",
+                self.frequency, MAX_FREQUENCY
+            ));
+        }
+        let profiles = [
+            ("zaxis_smoothing", &self.zaxis_smoothing),
+            ("left_joystick_smoothing", &self.left_joystick_smoothing),
+            ("right_joystick_smoothing", &self.right_joystick_smoothing),
+            ("default_axis_smoothing", &self.default_axis_smoothing),
+            ("scroll.smoothing", &self.scroll.smoothing),
+        ];
+        for (name, profile) in profiles {
+            profile
+                .validate()
+                .map_err(|e| format!("Invalid {}: {}", name, e))?;
+        }
+        let layer_buttons = self.layers.iter().flat_map(|(name, layer)| {
+            let mapping = layer.button_mapping.keys();
+            let names = std::iter::once(&layer.button).chain(mapping);
+            names.map(move |button| (format!("layers.{}", name), button))
+        });
+        let chord_buttons = self.chords.iter().enumerate().flat_map(|(i, chord)| {
+            let place = format!("chords[{}]", i);
+            chord.buttons.iter().map(move |button| (place.clone(), button))
+        });
+        let buttons = self.button_mapping.keys();
+        for (place, name) in buttons
+            .map(|button| ("button_mapping".to_string(), button))
+            .chain(layer_buttons)
+            .chain(chord_buttons)
+        {
+            if button_from_name(name).is_none() && name.parse::<u32>().is_err() {
+                return Err(format!(
+                    "Unknown button {:?} in {}, expected a name like South or a code",
+                    name, place
+                ));
+            }
+        }
+        let scroll_stick = match self.scroll.source {
+            ScrollSource::LeftStick => Some(Joystick::Left),
+            ScrollSource::RightStick => Some(Joystick::Right),
+            _ => None,
+        };
+        if self.fine_joystick_sensitivity.is_some()
+            && scroll_stick == Some(self.mouse_joystick.other())
+        {
+            return Err(format!(
+                "The {:?} stick cannot both scroll and move the cursor, change scroll.source or \
+                 remove fine_joystick_sensitivity",
+                self.mouse_joystick.other()
+            ));
+        }
+        // With an axis mapping, `mouse_joystick` is not what moves the cursor.
+        if self.axis_mapping.is_empty() && scroll_stick == Some(self.mouse_joystick) {
+            return Err(format!(
+                "The {:?} stick cannot both scroll and move the cursor, change scroll.source or \
+                 mouse_joystick",
+                self.mouse_joystick
+            ));
+        }
+        for (name, stick) in [
+            ("left_joystick", &self.left_joystick),
+            ("right_joystick", &self.right_joystick),
+        ] {
+            let inner = self.joystick_deadzone;
+            if !(0.0 <= inner && inner < stick.outer_deadzone && stick.outer_deadzone <= 1.0) {
+                return Err(format!(
+                    "{}.outer_deadzone is {}, it must be above joystick_deadzone ({}) and at most 1",
+                    name, stick.outer_deadzone, inner
+                ));
+            }
+        }
+        let mut layers: Vec<_> = self.layers.iter().collect();
+        // Sorted so the same file always gives the same error.
+        layers.sort_by_key(|(name, _)| *name);
+        for (i, (name, layer)) in layers.iter().enumerate() {
+            let button = &layer.button;
+            if let Some((other, _)) = layers[..i].iter().find(|(_, l)| &l.button == button) {
+                return Err(format!(
+                    "layers.{} and layers.{} both use {:?} as their button",
+                    other, name, button
+                ));
+            }
+            let layer_mappings = layers.iter().map(|(other, l)| {
+                (
+                    format!("layers.{}.button_mapping", other),
+                    &l.button_mapping,
+                )
+            });
+            let mappings = std::iter::once(("button_mapping".to_string(), &self.button_mapping))
+                .chain(layer_mappings);
+            for (place, mapping) in mappings {
+                if mapping.contains_key(button) {
+                    return Err(format!(
+                        "{:?} switches layers.{}, it cannot be mapped in {} too",
+                        button, name, place
+                    ));
+                }
+            }
+            if let Some(i) = self.chords.iter().position(|c| c.buttons.contains(button)) {
+                return Err(format!(
+                    "{:?} switches layers.{}, it cannot be in chords[{}] too",
+                    button, name, i
+                ));
+            }
+        }
+        if !(self.chord_window > 0.0 && Duration::try_from_secs_f32(self.chord_window).is_ok()) {
+            return Err(format!(
+                "chord_window must be a positive number of seconds, got {}",
+                self.chord_window
+            ));
+        }
+        let layer_bindings = self.layers.iter().flat_map(|(name, layer)| {
+            let place = format!("layers.{}.button_mapping", name);
+            layer.button_mapping.iter().map(move |b| (place.clone(), b))
+        });
+        let bindings = self.button_mapping.iter();
+        for (place, (button, binding)) in bindings
+            .map(|b| ("button_mapping".to_string(), b))
+            .chain(layer_bindings)
+        {
+            let result = match binding {
+                ButtonBinding::Action(_) => Ok(()),
+                ButtonBinding::Gestures(gestures) => gestures.validate(),
+                ButtonBinding::Repeat(repeat) => repeat.validate(),
+            };
+            result.map_err(|e| format!("Invalid {}.{}: {}", place, button, e))?;
+        }
+        for (i, chord) in self.chords.iter().enumerate() {
+            if chord.buttons.len() < 2 {
+                return Err(format!("chords[{}] needs at least two buttons", i));
+            }
+            // Names and codes are already known to parse, see above.
+            let resolved: Vec<_> = chord
+                .buttons
+                .iter()
+                .map(|name| (button_from_name(name), name.parse::<u32>().ok()))
+                .collect();
+            for (j, button) in resolved.iter().enumerate() {
+                if resolved[..j].contains(button) {
+                    return Err(format!(
+                        "chords[{}] has {:?} twice, a chord needs different buttons",
+                        i, chord.buttons[j]
+                    ));
+                }
+            }
+        }
+        for (i, modifier) in self.modifiers.iter().enumerate() {
+            modifier
+                .curve
+                .validate()
+                .map_err(|e| format!("Invalid modifiers[{}].curve: {}", i, e))?;
+        }
+        let layer_axes = self.layers.iter().map(|(name, layer)| {
+            (format!("layers.{}.axis_mapping", name), &layer.axis_mapping)
+        });
+        for (place, axis_mapping) in
+            std::iter::once(("axis_mapping".to_string(), &self.axis_mapping)).chain(layer_axes)
+        {
+            for (i, mapping) in axis_mapping.iter().enumerate() {
+                let is_stick = match mapping.input {
+                    AxisInput::Axis(axis) => Joystick::from_axis(axis).is_some(),
+                    AxisInput::Code(_) => false,
+                };
+                if is_stick && mapping.deadzone != 0.0 {
+                    return Err(format!(
+                        "{}[{}].deadzone does not apply to sticks, use joystick_deadzone",
+                        place, i
+                    ));
+                }
+                if let Some(curve) = &mapping.curve {
+                    curve
+                        .validate()
+                        .map_err(|e| format!("Invalid {}[{}].curve: {}", place, i, e))?;
+                }
+            }
+        }
+        let layer_axis_buttons = self
+            .layers
+            .iter()
+            .map(|(name, layer)| (format!("layers.{}.axis_buttons", name), &layer.axis_buttons));
+        for (place, axis_buttons) in
+            std::iter::once(("axis_buttons".to_string(), &self.axis_buttons))
+                .chain(layer_axis_buttons)
+        {
+            for (axis, buttons) in axis_buttons {
+                // At 0 or below a resting axis would already count as pushed.
+                if !(buttons.threshold > 0.0 && buttons.threshold <= 1.0) {
+                    return Err(format!(
+                        "{}.{}.threshold is {}, it must be above 0 and at most 1",
+                        place, axis, buttons.threshold
+                    ));
+                }
+            }
+        }
+        if let Some(ramp) = &self.acceleration_ramp {
+            ramp.validate()
+                .map_err(|e| format!("Invalid acceleration_ramp: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a gilrs button name like "South", the way it is written in the config.
+pub fn button_from_name(name: &str) -> Option<Button> {
+    let deserializer: StrDeserializer<serde::de::value::Error> = name.into_deserializer();
+    Button::deserialize(deserializer).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn frequency_must_be_in_range() {
+        for (frequency, valid) in [
+            (1.0, true),
+            (1000.0, true),
+            (0.5, false),
+            (2000.0, false),
+            (f32::NAN, false),
+            (f32::INFINITY, false),
+        ] {
+            let config = Config {
+                frequency,
+                ..Config::default()
+            };
+            assert_eq!(config.validate().is_ok(), valid, "frequency {}", frequency);
+        }
+    }
+
+    #[test]
+    fn scroll_stick_must_not_move_the_cursor() {
+        let mut config = Config::default();
+        config.scroll.source = match config.mouse_joystick {
+            Joystick::Left => ScrollSource::LeftStick,
+            Joystick::Right => ScrollSource::RightStick,
+        };
+        assert!(config.validate().is_err());
+
+        // Unless the axis mapping moves the cursor with something else.
+        config.axis_mapping = vec![AxisMapping {
+            input: AxisInput::Axis(Axis::RightZ),
+            target: AxisTarget::PointerX,
+            scale: 1.0,
+            curve: None,
+            deadzone: 0.0,
+        }];
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn axis_button_threshold_must_be_a_deflection() {
+        for (threshold, valid) in [(0.5, true), (1.0, true), (0.0, false), (-0.2, false)] {
+            let mut config = Config::default();
+            let buttons = AxisButtons {
+                negative: None,
+                positive: Some(ButtonAction::MouseLeft),
+                threshold,
+            };
+            config.axis_buttons.insert("DPadX".to_string(), buttons);
+            assert_eq!(config.validate().is_ok(), valid, "threshold {}", threshold);
+        }
+    }
+
+    /// Loads the default configuration saved as a file, with `edit` making it look like an
+    /// older one.
+    fn load_old_file(edit: impl FnOnce(&mut toml::Table)) -> Config {
+        let saved = toml::to_string(&Config::default()).unwrap();
+        let mut table: toml::Table = toml::from_str(&saved).unwrap();
+        edit(&mut table);
+        let path = std::env::temp_dir().join(format!("old-config-{}.toml", std::process::id()));
+        fs::write(&path, toml::to_string(&table).unwrap()).unwrap();
+        let config = Config::load_from_file(Some(&path));
+        fs::remove_file(&path).unwrap();
+        config.unwrap()
+    }
+
+    #[test]
+    fn aim_button_becomes_a_modifier() {
+        let config = load_old_file(|table| {
+            table.remove("modifiers");
+            table.insert("aim_button".to_string(), "LeftTrigger2".into());
+            table.insert("aim_sensitivity_factor".to_string(), 3.0.into());
+        });
+        assert_eq!(config.aim_button, None);
+        assert_eq!(config.aim_sensitivity_factor, None);
+        assert_eq!(config.modifiers, Config::default().modifiers);
+    }
+
+    #[test]
+    fn mouse_sensitivity_becomes_a_speed() {
+        let config = load_old_file(|table| {
+            table.remove("mouse_speed");
+            table.insert("mouse_sensitivity".to_string(), 4.0.into());
+            table.insert("frequency".to_string(), 100.0.into());
+        });
+        assert_eq!(config.mouse_sensitivity, None);
+        assert_eq!(config.mouse_speed, 400.0);
+    }
+
+    fn ramp() -> AccelerationRamp {
+        AccelerationRamp {
+            threshold: 0.5,
+            max_multiplier: 3.0,
+            duration: 2.0,
+        }
+    }
+
+    #[test]
+    fn ramp_grows_linearly_up_to_its_max() {
+        let ramp = ramp();
+        assert_eq!(ramp.multiplier(Duration::ZERO), 1.0);
+        assert_eq!(ramp.multiplier(Duration::from_millis(500)), 1.5);
+        assert_eq!(ramp.multiplier(Duration::from_secs(1)), 2.0);
+        assert_eq!(ramp.multiplier(Duration::from_secs(2)), 3.0);
+        assert_eq!(ramp.multiplier(Duration::from_secs(60)), 3.0);
+    }
+
+    #[test]
+    fn acceleration_ramp_is_validated() {
+        let bad_ramps = [
+            AccelerationRamp {
+                threshold: 0.0,
+                ..ramp()
+            },
+            AccelerationRamp {
+                threshold: -0.5,
+                ..ramp()
+            },
+            AccelerationRamp {
+                max_multiplier: f32::INFINITY,
+                ..ramp()
+            },
+            AccelerationRamp {
+                duration: -1.0,
+                ..ramp()
+            },
+            AccelerationRamp {
+                duration: f32::NAN,
+                ..ramp()
+            },
+        ];
+        for ramp in bad_ramps {
+            let config = Config {
+                acceleration_ramp: Some(ramp.clone()),
+                ..Config::default()
+            };
+            assert!(config.validate().is_err(), "{:?} was accepted", ramp);
+        }
+        let config = Config {
+            acceleration_ramp: Some(ramp()),
+            ..Config::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
 }
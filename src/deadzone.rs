@@ -0,0 +1,124 @@
+use crate::config::DeadzoneShape;
+
+/// Maps a raw stick position to a clean one: zero inside the inner deadzone, full length past
+/// the outer one, and (except for the plain radial shape) rescaled in between so the output
+/// starts from zero right at the deadzone edge instead of jumping.
+pub fn apply_deadzone(vector: [f32; 2], shape: &DeadzoneShape, inner: f32, outer: f32) -> [f32; 2] {
+    let [x, y] = vector;
+    let magnitude = x.hypot(y);
+    match shape {
+        DeadzoneShape::Axial => [rescale(x, inner, outer), rescale(y, inner, outer)],
+        DeadzoneShape::Radial => {
+            if magnitude < inner {
+                [0.0, 0.0]
+            } else {
+                clamp_length([x / outer, y / outer])
+            }
+        }
+        DeadzoneShape::ScaledRadial => {
+            // Also covers a centered stick with no deadzone, which would divide 0 by 0 below.
+            if magnitude <= inner {
+                return [0.0, 0.0];
+            }
+            let scale = rescale(magnitude, inner, outer) / magnitude;
+            [x * scale, y * scale]
+        }
+        DeadzoneShape::Hybrid => {
+            if magnitude <= inner {
+                return [0.0, 0.0];
+            }
+            // Scaled radial first so the output starts from zero at the edge, then a sloped
+            // axial on top: the deadzone of each axis grows with the other axis deflection, so
+            // a mostly horizontal push stays horizontal without snapping at small angles.
+            let scale = rescale(magnitude, inner, outer) / magnitude;
+            let [x, y] = [x * scale, y * scale];
+            let x_out = rescale(x, inner * y.abs(), 1.0);
+            let y_out = rescale(y, inner * x.abs(), 1.0);
+            clamp_length([x_out, y_out])
+        }
+    }
+}
+
+/// Maps |value| from [inner, outer] to [0, 1], keeping its sign. The deadzone of a single axis.
+pub fn rescale(value: f32, inner: f32, outer: f32) -> f32 {
+    if value.abs() <= inner {
+        return 0.0;
+    }
+    let range = (outer - inner).max(f32::EPSILON);
+    value.signum() * ((value.abs() - inner) / range).min(1.0)
+}
+
+fn clamp_length(vector: [f32; 2]) -> [f32; 2] {
+    let [x, y] = vector;
+    let magnitude = x.hypot(y);
+    if magnitude > 1.0 {
+        [x / magnitude, y / magnitude]
+    } else {
+        vector
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALED_SHAPES: [DeadzoneShape; 3] = [
+        DeadzoneShape::Axial,
+        DeadzoneShape::ScaledRadial,
+        DeadzoneShape::Hybrid,
+    ];
+
+    fn length(vector: [f32; 2]) -> f32 {
+        vector[0].hypot(vector[1])
+    }
+
+    #[test]
+    fn scaled_shapes_start_from_zero_at_the_edge() {
+        for shape in &SCALED_SHAPES {
+            let just_out = apply_deadzone([0.21, 0.01], shape, 0.2, 1.0);
+            assert!(length(just_out) < 0.02, "{:?} gave {:?}", shape, just_out);
+            let diagonal = apply_deadzone([0.15, 0.15], shape, 0.2, 1.0);
+            assert!(length(diagonal) < 0.02, "{:?} gave {:?}", shape, diagonal);
+        }
+    }
+
+    #[test]
+    fn full_push_reaches_full_length() {
+        for shape in &SCALED_SHAPES {
+            let pushed = apply_deadzone([0.9, 0.0], shape, 0.2, 0.9);
+            assert!(
+                (length(pushed) - 1.0).abs() < 1e-5,
+                "{:?} gave {:?}",
+                shape,
+                pushed
+            );
+        }
+    }
+
+    #[test]
+    fn no_deadzone_keeps_a_centered_stick_at_zero() {
+        let shapes = [
+            DeadzoneShape::Axial,
+            DeadzoneShape::Radial,
+            DeadzoneShape::ScaledRadial,
+            DeadzoneShape::Hybrid,
+        ];
+        for shape in &shapes {
+            assert_eq!(apply_deadzone([0.0, 0.0], shape, 0.0, 1.0), [0.0, 0.0]);
+            let [x, y] = apply_deadzone([0.5, 0.0], shape, 0.0, 1.0);
+            assert!(
+                (x - 0.5).abs() < 1e-5 && y == 0.0,
+                "{:?} gave {:?}",
+                shape,
+                [x, y]
+            );
+        }
+    }
+
+    #[test]
+    fn hybrid_keeps_mostly_straight_pushes_straight() {
+        let [x, y] = apply_deadzone([0.8, 0.05], &DeadzoneShape::Hybrid, 0.2, 1.0);
+        assert!(x > 0.7);
+        assert_eq!(y, 0.0);
+    }
+}
@@ -0,0 +1,208 @@
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use crate::config::StickFilter;
+
+/// Stick deflection the stick must be released from for a snapback to be suspected.
+const RELEASE_FROM: f32 = 0.3;
+/// Speed towards the center, in units per second, past which a return is a release rather than
+/// the user moving back. Springs bring a stick back in a few milliseconds, way above that.
+const RELEASE_SPEED: f32 = 10.0;
+
+/// Filtering state of one axis. Fed with every raw value along with the time it was measured,
+/// on the frame clock: the frame updates it too while the stick sends nothing.
+#[derive(Debug, Clone, Default)]
+pub struct AxisFilter {
+    last_time: Option<Instant>,
+    /// Filtered value
+    pub value: f32,
+    /// Filtered speed of the value, in units per second, only used by the One Euro filter.
+    derivative: f32,
+}
+
+impl AxisFilter {
+    pub fn update(&mut self, filter: &StickFilter, raw: f32, time: Instant) {
+        if *filter == StickFilter::None {
+            self.last_time = Some(time);
+            self.value = raw;
+            return;
+        }
+        let Some(last_time) = self.last_time else {
+            self.last_time = Some(time);
+            self.value = raw;
+            return;
+        };
+        // Events can share a timestamp, or be stamped a bit after the frame reading them.
+        let dt = time.saturating_duration_since(last_time);
+        if dt.is_zero() {
+            return;
+        }
+        let dt = dt.as_secs_f32();
+        self.last_time = Some(time);
+
+        self.value = match *filter {
+            StickFilter::None => raw,
+            StickFilter::Ema { time_constant } => {
+                let alpha = 1.0 - (-dt / time_constant.max(f32::EPSILON)).exp();
+                lerp(self.value, raw, alpha)
+            }
+            StickFilter::OneEuro {
+                min_cutoff,
+                beta,
+                derivative_cutoff,
+            } => {
+                // The faster the stick moves, the higher the cutoff: slow movement gets
+                // smoothed while flicks go through with little lag.
+                let speed = (raw - self.value) / dt;
+                self.derivative = lerp(self.derivative, speed, alpha(derivative_cutoff, dt));
+                let cutoff = min_cutoff + beta * self.derivative.abs();
+                lerp(self.value, raw, alpha(cutoff, dt))
+            }
+        };
+    }
+}
+
+/// Hides the overshoot of a stick released sharply: its spring sends it past the center for a
+/// few milliseconds, which would otherwise jerk the cursor the other way.
+#[derive(Debug, Clone, Default)]
+pub struct Snapback {
+    last: [f32; 2],
+    last_time: Option<Instant>,
+    /// Position the stick was released from and when, while we are hiding the overshoot.
+    released: Option<([f32; 2], Instant)>,
+}
+
+impl Snapback {
+    /// Returns the stick position to use, centered while it overshoots during `window` seconds
+    /// after a release.
+    pub fn update(&mut self, raw: [f32; 2], time: Instant, window: f32) -> [f32; 2] {
+        let previous = self.last;
+        let dt = self
+            .last_time
+            .and_then(|last_time| time.checked_duration_since(last_time))
+            .map(|dt| dt.as_secs_f32());
+        self.last = raw;
+        if dt.is_some() || self.last_time.is_none() {
+            self.last_time = Some(time);
+        }
+
+        // X and Y come in separate events, often with the same timestamp. While hiding an
+        // overshoot we do not look for another release, coming back from it is just as fast.
+        if let Some(dt) = dt.filter(|dt| *dt > 0.0)
+            && self.released.is_none()
+        {
+            let magnitude = |[x, y]: [f32; 2]| x.hypot(y);
+            let return_speed = (magnitude(previous) - magnitude(raw)) / dt;
+            if magnitude(previous) >= RELEASE_FROM && return_speed >= RELEASE_SPEED {
+                self.released = Some((previous, time));
+            }
+        }
+
+        let Some((released_from, since)) = self.released else {
+            return raw;
+        };
+        let elapsed = time.saturating_duration_since(since).as_secs_f32();
+        if elapsed > window {
+            self.released = None;
+            return raw;
+        }
+        let same_side = raw[0] * released_from[0] + raw[1] * released_from[1] >= 0.0;
+        if same_side { raw } else { [0.0, 0.0] }
+    }
+}
+
+/// Smoothing factor of a first order low pass filter with the given cutoff frequency in hertz.
+fn alpha(cutoff: f32, dt: f32) -> f32 {
+    let tau = 1.0 / (2.0 * PI * cutoff.max(f32::EPSILON));
+    1.0 / (1.0 + tau / dt)
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gesture::at;
+
+    /// How far behind the stick the filter is, in seconds, after pushing it from the center to
+    /// half way at `speed` units per second, read every millisecond.
+    fn time_lag(filter: &StickFilter, speed: f32) -> f32 {
+        let mut axis = AxisFilter::default();
+        let steps = (500.0 / speed) as u64;
+        for ms in 0..=steps {
+            axis.update(filter, speed * ms as f32 / 1000.0, at(ms));
+        }
+        (0.5 - axis.value) / speed
+    }
+
+    #[test]
+    fn ema_catches_up_with_a_still_stick() {
+        let filter = StickFilter::Ema {
+            time_constant: 0.03,
+        };
+        let mut axis = AxisFilter::default();
+        axis.update(&filter, 0.0, at(0));
+        axis.update(&filter, 0.8, at(30));
+        assert!(axis.value > 0.4 && axis.value < 0.6, "got {}", axis.value);
+        for ms in (40..=300).step_by(10) {
+            axis.update(&filter, 0.8, at(ms));
+        }
+        assert!((axis.value - 0.8).abs() < 1e-3, "got {}", axis.value);
+    }
+
+    #[test]
+    fn one_euro_lags_less_on_fast_moves() {
+        let filter = StickFilter::OneEuro {
+            min_cutoff: 1.0,
+            beta: 5.0,
+            derivative_cutoff: 1.0,
+        };
+        let slow = time_lag(&filter, 1.0);
+        let fast = time_lag(&filter, 10.0);
+        assert!(fast < slow * 0.7, "fast {} s behind, slow {} s", fast, slow);
+
+        // A fixed cutoff lags the same whatever the speed.
+        let ema = StickFilter::Ema {
+            time_constant: 0.03,
+        };
+        let (slow, fast) = (time_lag(&ema, 1.0), time_lag(&ema, 10.0));
+        assert!(fast > slow * 0.7, "fast {} s behind, slow {} s", fast, slow);
+    }
+
+    #[test]
+    fn snapback_hides_the_overshoot_during_the_window() {
+        let mut snapback = Snapback::default();
+        assert_eq!(snapback.update([1.0, 0.0], at(0), 0.05), [1.0, 0.0]);
+        // Let go, the spring throws the stick past the center.
+        assert_eq!(snapback.update([-0.4, 0.0], at(4), 0.05), [0.0, 0.0]);
+        assert_eq!(snapback.update([-0.2, 0.0], at(20), 0.05), [0.0, 0.0]);
+        // Once the window is over, the same position is a real push again.
+        assert_eq!(snapback.update([-0.2, 0.0], at(60), 0.05), [-0.2, 0.0]);
+    }
+
+    #[test]
+    fn snapback_lets_slow_moves_through() {
+        let mut snapback = Snapback::default();
+        snapback.update([1.0, 0.0], at(0), 0.05);
+        // Brought back by hand, at 5 units per second.
+        assert_eq!(snapback.update([0.0, 0.0], at(200), 0.05), [0.0, 0.0]);
+        assert_eq!(snapback.update([-0.4, 0.0], at(280), 0.05), [-0.4, 0.0]);
+    }
+
+    #[test]
+    fn one_euro_settles_on_a_still_stick() {
+        let filter = StickFilter::OneEuro {
+            min_cutoff: 1.0,
+            beta: 5.0,
+            derivative_cutoff: 1.0,
+        };
+        let mut axis = AxisFilter::default();
+        axis.update(&filter, 0.0, at(0));
+        for ms in (10..=2000).step_by(10) {
+            axis.update(&filter, 0.8, at(ms));
+        }
+        assert!((axis.value - 0.8).abs() < 1e-3, "got {}", axis.value);
+    }
+}
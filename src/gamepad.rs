@@ -1,17 +1,117 @@
-use gilrs::{EventType, Gilrs};
+use gilrs::{Axis, Button, EventType, Gilrs};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::config::{AccelerationProfile, Config};
-use crate::mouse::{click_control, movement_control, MouseMovementInput};
-use crate::smoothing::smooth_profile;
+use crate::config::{
+    AccelerationProfile, AxisButtons, AxisInput, AxisMapping, AxisTarget, ButtonAction,
+    ButtonBinding, Config, CurveMode, DeadzoneShape, Joystick, Layer, LayerActivation,
+    ModifierInput, ModifierMode, ScrollSource,
+};
+use crate::deadzone::{apply_deadzone, rescale};
+use crate::filter::{AxisFilter, Snapback};
+use crate::gesture::{ActionChange, GestureTracker};
+use crate::keyboard::{combo_control, key_control};
+use crate::keys::Key;
+use crate::mouse::{
+    click_control, movement_control, scroll_control, MouseMovementInput, ScrollInput,
+};
+use crate::output::{platform_sink, MouseButton, OutputSink};
+use crate::repeat::Repeater;
+use crate::smoothing::{smooth_profile, smooth_vector};
+
+/// Longest frame we account for. After a longer stall (window hidden, machine asleep) we move as
+/// if only this much time had passed, rather than teleporting the cursor.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// How far an input must be pressed to flip a toggle modifier.
+const TOGGLE_THRESHOLD: f32 = 0.5;
+
+/// Sum of the axis mapping table entries for one frame.
+struct MappedAxes {
+    pointer: [f32; 2],
+    wheel: [f32; 2],
+    sensitivity: f32,
+}
+
+/// A gamepad event reduced to what we look at, so events can come from elsewhere than gilrs.
+/// Codes are the raw event codes, see `gilrs::ev::Code::into_u32`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PadEvent {
+    AxisChanged(Axis, f32, u32),
+    ButtonChanged(Button, f32, u32),
+    ButtonPressed(Button, u32),
+    ButtonReleased(Button, u32),
+}
+
+impl PadEvent {
+    fn from_gilrs(event: EventType) -> Option<Self> {
+        match event {
+            EventType::AxisChanged(axis, value, code) => {
+                Some(Self::AxisChanged(axis, value, code.into_u32()))
+            }
+            EventType::ButtonChanged(btn, value, code) => {
+                Some(Self::ButtonChanged(btn, value, code.into_u32()))
+            }
+            EventType::ButtonPressed(btn, code) => Some(Self::ButtonPressed(btn, code.into_u32())),
+            EventType::ButtonReleased(btn, code) => {
+                Some(Self::ButtonReleased(btn, code.into_u32()))
+            }
+            _ => None,
+        }
+    }
+}
+
+struct PendingPress {
+    btn: Button,
+    code: u32,
+    time: Instant,
+}
+
+/// A chord that fired, until all of its buttons are released.
+struct HeldChord {
+    /// Buttons still down
+    codes: Vec<u32>,
+    /// Taken out once released, on the first button going up
+    action: Option<ButtonAction>,
+}
 
 pub struct GamepadHandler {
-    gilrs: Gilrs,
+    /// None when the events only come through `handle_event`.
+    gilrs: Option<Gilrs>,
     pub config: Config,
     mouse_input: MouseMovementInput,
+    scroll_input: ScrollInput,
+    /// Last raw value of each axis, before any deadzone or smoothing.
+    axis_values: HashMap<Axis, f32>,
+    /// Last raw value of each axis by event code, the only way to tell unknown axes apart.
+    code_values: HashMap<u32, f32>,
+    /// Stick axes after snapback suppression and filtering.
+    axis_filters: HashMap<Axis, AxisFilter>,
+    snapbacks: HashMap<Joystick, Snapback>,
+    /// Last analog value of each button, 0 or 1 for the digital ones.
+    button_values: HashMap<Button, f32>,
+    /// Whether each of `config.modifiers` is switched on, only used by toggle modifiers.
+    toggled_modifiers: Vec<bool>,
+    /// Buttons bound to gestures, by event code.
+    gesture_trackers: HashMap<u32, GestureTracker>,
+    /// Direction each entry of `axis_buttons` is currently pushed in, and the action it pressed.
+    axis_directions: HashMap<String, (i8, Option<ButtonAction>)>,
+    /// Actions of the buttons currently down, by event code.
+    held_buttons: HashMap<u32, ButtonAction>,
+    /// Held buttons bound to a repeated action, by event code.
+    repeaters: HashMap<u32, Repeater>,
+    active_layer: Option<String>,
+    /// Presses of chord buttons, waiting for the rest of the chord.
+    pending_presses: Vec<PendingPress>,
+    held_chords: Vec<HeldChord>,
+    /// How long the mouse stick has been past the acceleration ramp threshold.
+    ramp_held: Duration,
+    output: Box<dyn OutputSink>,
 }
 
 impl GamepadHandler {
     pub fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let output = platform_sink(config.output_backend)?;
         let gilrs = Gilrs::new()?;
 
         println!("Looking for controllers...");
@@ -23,110 +123,1110 @@ impl GamepadHandler {
             );
         }
 
+        Ok(Self::with_output(config, Some(gilrs), output))
+    }
+
+    /// Same as `new` but sending everything to the given sink instead of the platform one.
+    /// Without `gilrs`, events are only fed through `handle_event`.
+    pub(crate) fn with_output(
+        config: Config,
+        gilrs: Option<Gilrs>,
+        output: Box<dyn OutputSink>,
+    ) -> Self {
         let mouse_input = MouseMovementInput {
             movement_vector: [0.0, 0.0],
-            sensitivity_factor: config.mouse_sensitivity,
-            deadzone: config.joystick_deadzone,
+            remainder: [0.0, 0.0],
         };
 
-        Ok(Self {
+        let scroll_input = ScrollInput {
+            sensitivity_factor: config.scroll.sensitivity,
+            ..ScrollInput::default()
+        };
+
+        Self {
             gilrs,
+            toggled_modifiers: vec![false; config.modifiers.len()],
             config,
             mouse_input,
-        })
+            scroll_input,
+            axis_values: HashMap::new(),
+            code_values: HashMap::new(),
+            axis_filters: HashMap::new(),
+            snapbacks: HashMap::new(),
+            button_values: HashMap::new(),
+            gesture_trackers: HashMap::new(),
+            axis_directions: HashMap::new(),
+            held_buttons: HashMap::new(),
+            repeaters: HashMap::new(),
+            active_layer: None,
+            pending_presses: Vec::new(),
+            held_chords: Vec::new(),
+            ramp_held: Duration::ZERO,
+            output,
+        }
     }
 
-    pub fn process_frame(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.process_events()?;
+    /// Where the events go, for the status.
+    pub fn output_description(&self) -> String {
+        self.output.describe()
+    }
 
-        let deadzone = self.config.joystick_deadzone;
-        if self.mouse_input.movement_vector[0].abs() > deadzone
-            || self.mouse_input.movement_vector[1].abs() > deadzone
-        {
-            movement_control(&self.mouse_input);
+    /// Swaps the configuration while running. The output backend is only recreated if it changed.
+    pub fn set_config(&mut self, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+        // The layers and gestures might not exist anymore, better not leave anything pressed.
+        self.release_all()?;
+        self.active_layer = None;
+        if config.output_backend != self.config.output_backend {
+            self.output = platform_sink(config.output_backend)?;
         }
+        self.scroll_input.sensitivity_factor = config.scroll.sensitivity;
+        self.toggled_modifiers = vec![false; config.modifiers.len()];
+        self.config = config;
+        self.axis_filters.clear();
+        self.snapbacks.clear();
+        Ok(())
+    }
+
+    /// Reads the pending gamepad events and moves the mouse by what `elapsed` is worth, so the
+    /// speed does not depend on how often we are called.
+    pub fn process_frame(&mut self, elapsed: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let now = Instant::now();
+        let events = self.process_events(now);
+        // A failing event must not cost the frame its movement and timeouts.
+        let updated = self.update(elapsed, now);
+        // Even after an error, what the frame did so far must reach the OS.
+        self.output.flush()?;
+        events?;
+        updated
+    }
+
+    /// The part of a frame that does not depend on gilrs: timeouts, then the pointer and wheel
+    /// movement for the events handled so far.
+    pub(crate) fn update(
+        &mut self,
+        elapsed: Duration,
+        now: Instant,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let elapsed = elapsed.min(MAX_FRAME_TIME);
+
+        // Sticks held still send no events, the filters still need time to catch up with them.
+        self.flush_pending_presses(now)?;
+        self.update_gestures(now)?;
+        self.update_repeaters(now)?;
+        self.update_stick(Joystick::Left, now);
+        self.update_stick(Joystick::Right, now);
+
+        let mapped = self.mapped_axes()?;
+        let speed = self.config.mouse_speed * self.sensitivity_modifier()? * mapped.sensitivity;
+        let (velocity, wheel) = if self.axis_mapping().is_empty() {
+            let joystick = self.config.mouse_joystick;
+            let vector = self.stick_vector(joystick, self.axis_profile(&joystick.x_axis()))?;
+            let multiplier = self.ramp_multiplier(vector, elapsed);
+            let settings = joystick.settings(&self.config);
+            let mut velocity = settings.scale(vector.map(|v| v * multiplier * speed));
+            if let Some(fine_speed) = self.config.fine_joystick_sensitivity {
+                // At its own speed, the modifiers and the ramp are for the main stick.
+                let fine = joystick.other();
+                let profile = self.axis_profile(&fine.x_axis());
+                let vector = self.stick_vector(fine, profile)?;
+                let vector = fine.settings(&self.config).scale(vector);
+                velocity[0] += vector[0] * fine_speed;
+                velocity[1] += vector[1] * fine_speed;
+            }
+            (velocity, self.scroll_vector()?)
+        } else {
+            let multiplier = self.ramp_multiplier(mapped.pointer, elapsed);
+            (mapped.pointer.map(|v| v * multiplier * speed), mapped.wheel)
+        };
+
+        self.mouse_input.movement_vector = velocity;
+        movement_control(self.output.as_mut(), &mut self.mouse_input, elapsed)?;
+
+        self.scroll_input.scroll_vector = wheel;
+        scroll_control(self.output.as_mut(), &mut self.scroll_input, elapsed)?;
 
         Ok(())
     }
 
-    fn process_events(&mut self) -> Result<(), String> {
-        while let Some(gil_event) = self.gilrs.next_event() {
-            match gil_event.event {
-                EventType::AxisChanged(axis, value, _) => {
-                    let smoothed_value = self.smooth_axis_event_value(&axis, value)?;
-                    if axis == self.config.mouse_joystick.x_axis() {
-                        self.mouse_input.movement_vector[0] = smoothed_value;
-                    } else if axis == self.config.mouse_joystick.y_axis() {
-                        self.mouse_input.movement_vector[1] = smoothed_value;
-                    }
+    /// Stick position after its rotation, deadzone and `profile`.
+    fn stick_vector(
+        &self,
+        joystick: Joystick,
+        profile: &AccelerationProfile,
+    ) -> Result<[f32; 2], &'static str> {
+        let settings = joystick.settings(&self.config);
+        let raw = [
+            self.axis_value(joystick.x_axis()),
+            self.axis_value(joystick.y_axis()),
+        ];
+        let vector = apply_deadzone(
+            settings.rotate(raw),
+            &settings.deadzone_shape,
+            self.config.joystick_deadzone,
+            settings.outer_deadzone,
+        );
+        smooth_vector(vector, profile, &settings.curve_mode)
+    }
+
+    /// Speed multiplier of the acceleration ramp, 1 when there is none or the stick is not
+    /// pushed far enough.
+    fn ramp_multiplier(&mut self, vector: [f32; 2], elapsed: Duration) -> f32 {
+        let Some(ramp) = &self.config.acceleration_ramp else {
+            return 1.0;
+        };
+        if vector[0].hypot(vector[1]) < ramp.threshold {
+            self.ramp_held = Duration::ZERO;
+            return 1.0;
+        }
+        self.ramp_held += elapsed;
+        ramp.multiplier(self.ramp_held)
+    }
+
+    /// Runs the raw values of a stick through its snapback suppression and filter.
+    fn update_stick(&mut self, joystick: Joystick, time: Instant) {
+        let settings = joystick.settings(&self.config);
+        let axes = [joystick.x_axis(), joystick.y_axis()];
+        let raw = axes.map(|axis| self.axis_values.get(&axis).copied().unwrap_or(0.0));
+        let vector = if settings.snapback_window > 0.0 {
+            self.snapbacks
+                .entry(joystick)
+                .or_default()
+                .update(raw, time, settings.snapback_window)
+        } else {
+            raw
+        };
+        for (axis, value) in axes.into_iter().zip(vector) {
+            self.axis_filters
+                .entry(axis)
+                .or_default()
+                .update(&settings.filter, value, time);
+        }
+    }
+
+    /// What the axis mapping table asks for, all zero and a sensitivity of 1 when it is empty.
+    fn mapped_axes(&self) -> Result<MappedAxes, &'static str> {
+        let mut mapped = MappedAxes {
+            pointer: [0.0, 0.0],
+            wheel: [0.0, 0.0],
+            sensitivity: 1.0,
+        };
+        for mapping in self.axis_mapping() {
+            let value = self.mapping_value(mapping)?;
+            let scaled = value * mapping.scale;
+            match mapping.target {
+                AxisTarget::PointerX => mapped.pointer[0] += scaled,
+                AxisTarget::PointerY => mapped.pointer[1] += scaled,
+                AxisTarget::WheelX => mapped.wheel[0] += scaled,
+                AxisTarget::WheelY => mapped.wheel[1] += scaled,
+                // Axes going both ways count how far they are pushed.
+                AxisTarget::Sensitivity => {
+                    mapped.sensitivity *= (1.0 + value.abs() * mapping.scale).max(0.0)
                 }
-                EventType::ButtonChanged(id, value, _code) => {
-                    if id == self.config.aim_button {
-                        let smoothed_value =
-                            self.smooth_button_event_value(&self.config.aim_button, value)?;
-                        let p = self.config.aim_sensitivity_factor;
-                        let m = 1. - self.config.aim_sensitivity_factor;
-                        let x = 1. - smoothed_value;
-                        let modifier = m * x + p;
-
-                        self.mouse_input.sensitivity_factor =
-                            self.config.mouse_sensitivity * modifier;
-                    }
+            }
+        }
+        Ok(mapped)
+    }
+
+    /// Input of an axis mapping after its deadzone and curve. Stick axes go through their stick
+    /// settings like without a mapping: rotation, deadzone shape, curve mode, sensitivity and
+    /// inversion.
+    fn mapping_value(&self, mapping: &AxisMapping) -> Result<f32, &'static str> {
+        let (value, default_curve) = match mapping.input {
+            AxisInput::Axis(axis) => {
+                if let Some(joystick) = Joystick::from_axis(axis) {
+                    let curve = mapping.curve.as_ref().unwrap_or(self.axis_profile(&axis));
+                    let settings = joystick.settings(&self.config);
+                    let [x, y] = settings.scale(self.stick_vector(joystick, curve)?);
+                    return Ok(if axis == joystick.x_axis() { x } else { y });
                 }
-                event @ EventType::ButtonPressed(_btn, code)
-                | event @ EventType::ButtonReleased(_btn, code) => {
-                    let key = code.into_u32().to_string();
-                    if self.config.button_mapping.contains_key(&key) {
-                        self.handle_button(&self.config.button_mapping[&key], &event);
+                (self.axis_value(axis), self.axis_profile(&axis))
+            }
+            AxisInput::Code(code) => (
+                self.code_values.get(&code).copied().unwrap_or(0.0),
+                &self.config.default_axis_smoothing,
+            ),
+        };
+        let value = rescale(value, mapping.deadzone, 1.0);
+        smooth_profile(value, mapping.curve.as_ref().unwrap_or(default_curve))
+    }
+
+    /// Axis value after filtering, before any deadzone or smoothing.
+    fn axis_value(&self, axis: Axis) -> f32 {
+        match self.axis_filters.get(&axis) {
+            Some(filter) => filter.value,
+            None => self.axis_values.get(&axis).copied().unwrap_or(0.0),
+        }
+    }
+
+    /// Scroll direction and strength from the configured source, after its deadzone and profile.
+    fn scroll_vector(&self) -> Result<[f32; 2], &str> {
+        let value = |axis: Axis| self.axis_value(axis);
+        let scroll = &self.config.scroll;
+        let raw = match scroll.source {
+            ScrollSource::Disabled => return Ok([0.0, 0.0]),
+            ScrollSource::LeftStick => [value(Axis::LeftStickX), value(Axis::LeftStickY)],
+            ScrollSource::RightStick => [value(Axis::RightStickX), value(Axis::RightStickY)],
+            ScrollSource::Triggers => [0.0, value(Axis::RightZ) - value(Axis::LeftZ)],
+        };
+        // Axial on purpose, scrolling mostly wants to stick to one direction.
+        let vector = apply_deadzone(raw, &DeadzoneShape::Axial, scroll.deadzone, 1.0);
+        smooth_vector(vector, &scroll.smoothing, &CurveMode::PerAxis)
+    }
+
+    /// Handles every queued event. One failing does not stop the others, the last error is
+    /// returned once they are all done.
+    fn process_events(&mut self, now: Instant) -> Result<(), String> {
+        let mut result = Ok(());
+        while let Some(gil_event) = self.gilrs.as_mut().and_then(Gilrs::next_event) {
+            let Some(event) = PadEvent::from_gilrs(gil_event.event) else {
+                continue;
+            };
+            // Chords, gestures and repeats are timed out against the `now` of `update`, so
+            // buttons go by the frame clock as well. The stick filters want to know when the
+            // axis moved, but the driver stamps events with the wall clock, which can jump:
+            // only the age of the event is kept, moved onto our clock.
+            let time = match event {
+                PadEvent::AxisChanged(..) => {
+                    let age = SystemTime::now()
+                        .duration_since(gil_event.time)
+                        .unwrap_or_default();
+                    now.checked_sub(age.min(MAX_FRAME_TIME)).unwrap_or(now)
+                }
+                _ => now,
+            };
+            if let Err(e) = self.handle_event(event, time) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Handles one event, `time` being when it happened. For buttons it must be on the same clock
+    /// as the `now` given to `update`.
+    pub(crate) fn handle_event(&mut self, event: PadEvent, time: Instant) -> Result<(), String> {
+        match event {
+            PadEvent::AxisChanged(axis, value, code) => {
+                // Unknown axes would all share the same entry, they can only be used by code.
+                self.code_values.insert(code, value);
+                if axis != Axis::Unknown {
+                    // Deadzones and smoothing need both axes of a stick, see `process_frame`.
+                    self.set_axis_value(axis, value);
+                }
+                if let Some(joystick) = Joystick::from_axis(axis) {
+                    self.update_stick(joystick, time);
+                }
+                self.axis_buttons(axis, code, value)?;
+            }
+            PadEvent::ButtonChanged(id, value, code) => {
+                // A layer button does nothing else, not even move a trigger axis or a modifier.
+                if self.layer_of_button(id, code).is_some() {
+                    return Ok(());
+                }
+                // Most drivers report the triggers as analog buttons rather than Z axes.
+                match id {
+                    Button::LeftTrigger2 => self.set_axis_value(Axis::LeftZ, value),
+                    Button::RightTrigger2 => self.set_axis_value(Axis::RightZ, value),
+                    _ => {}
+                }
+                let previous = self.button_values.insert(id, value).unwrap_or(0.0);
+                self.update_toggles(ModifierInput::Button(id), previous, value);
+            }
+            PadEvent::ButtonPressed(btn, code) => {
+                if let Some(layer) = self.layer_of_button(btn, code) {
+                    return self.switch_layer(layer, true);
+                }
+                if self.in_chord(btn, code) {
+                    // Held back until we know whether the rest of a chord follows.
+                    self.flush_pending_presses(time)?;
+                    self.pending_presses.push(PendingPress { btn, code, time });
+                    return self.detect_chords();
+                }
+                self.press_button(btn, code, time)?;
+            }
+            PadEvent::ButtonReleased(btn, code) => {
+                if let Some(layer) = self.layer_of_button(btn, code) {
+                    return self.switch_layer(layer, false);
+                }
+                if let Some(i) = self.held_chords.iter().position(|c| c.codes.contains(&code)) {
+                    // The first release ends the chord, the others are swallowed.
+                    let chord = &mut self.held_chords[i];
+                    chord.codes.retain(|held| *held != code);
+                    let action = chord.action.take();
+                    if chord.codes.is_empty() {
+                        self.held_chords.remove(i);
                     }
+                    if let Some(action) = action {
+                        self.handle_button(&action, false)?;
+                    }
+                } else if let Some(i) = self.pending_presses.iter().position(|p| p.code == code) {
+                    // Released before the chord window ended, still a normal press.
+                    let press = self.pending_presses.remove(i);
+                    self.press_button(press.btn, press.code, press.time)?;
+                    self.release_button(code, time)?;
+                } else {
+                    self.release_button(code, time)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn press_button(&mut self, btn: Button, code: u32, time: Instant) -> Result<(), String> {
+        match self.button_binding(btn, code).cloned() {
+            Some(ButtonBinding::Action(action)) => {
+                self.held_buttons.insert(code, action.clone());
+                self.handle_button(&action, true)?;
+            }
+            Some(ButtonBinding::Gestures(gestures)) => {
+                let tracker = self
+                    .gesture_trackers
+                    .entry(code)
+                    .or_insert_with(|| GestureTracker::new(gestures.clone()));
+                if tracker.is_idle() {
+                    tracker.set_gestures(gestures);
                 }
-                _ => {}
+                let changes = tracker.press(time);
+                self.apply_changes(changes)?;
+            }
+            Some(ButtonBinding::Repeat(binding)) => {
+                let action = binding.action.clone();
+                self.repeaters.insert(code, Repeater::new(binding, time));
+                self.handle_button(&action, true)?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn release_button(&mut self, code: u32, time: Instant) -> Result<(), String> {
+        // Released the way it was pressed, even if the mapping changed in between.
+        if let Some(action) = self.held_buttons.remove(&code) {
+            self.handle_button(&action, false)?;
+        } else if let Some(repeater) = self.repeaters.remove(&code) {
+            self.apply_changes(repeater.release())?;
+        } else if let Some(tracker) = self.gesture_trackers.get_mut(&code) {
+            let changes = tracker.release(time);
+            self.apply_changes(changes)?;
+        }
+        Ok(())
+    }
+
+    fn in_chord(&self, btn: Button, code: u32) -> bool {
+        let names = button_names(btn, code);
+        self.config
+            .chords
+            .iter()
+            .any(|chord| chord.buttons.iter().any(|button| names.contains(button)))
+    }
+
+    /// Fires the first chord whose buttons are all among the pending presses.
+    fn detect_chords(&mut self) -> Result<(), String> {
+        for chord in &self.config.chords {
+            let presses: Option<Vec<usize>> = chord
+                .buttons
+                .iter()
+                .map(|button| {
+                    self.pending_presses
+                        .iter()
+                        .position(|press| button_names(press.btn, press.code).contains(button))
+                })
+                .collect();
+            let Some(mut presses) = presses else {
+                continue;
+            };
+            presses.sort_unstable();
+            presses.dedup();
+            // A name and its raw code both match the same press, it still takes one per button.
+            if presses.len() < chord.buttons.len() {
+                continue;
+            }
+            let mut codes = Vec::new();
+            for i in presses.into_iter().rev() {
+                codes.push(self.pending_presses.remove(i).code);
+            }
+            let action = chord.action.clone();
+            self.held_chords.push(HeldChord {
+                codes,
+                action: Some(action.clone()),
+            });
+            return self.handle_button(&action, true);
+        }
+        Ok(())
+    }
+
+    /// Presses that waited `chord_window` without completing a chord do their own action.
+    fn flush_pending_presses(&mut self, now: Instant) -> Result<(), String> {
+        let window = Duration::from_secs_f32(self.config.chord_window);
+        let (expired, pending) = self.pending_presses.drain(..).partition(|press| {
+            now.saturating_duration_since(press.time) >= window
+        });
+        self.pending_presses = pending;
+        for press in expired {
+            self.press_button(press.btn, press.code, press.time)?;
+        }
+        Ok(())
+    }
+
+    /// Timeouts of the gestures in progress, like a button held long enough to count as held.
+    fn update_gestures(&mut self, now: Instant) -> Result<(), String> {
+        let changes: Vec<ActionChange> = self
+            .gesture_trackers
+            .values_mut()
+            .flat_map(|tracker| tracker.update(now))
+            .collect();
+        self.apply_changes(changes)
+    }
+
+    /// Repeats the actions of the held buttons bound to `RepeatBinding`s.
+    fn update_repeaters(&mut self, now: Instant) -> Result<(), String> {
+        let changes: Vec<ActionChange> = self
+            .repeaters
+            .values_mut()
+            .flat_map(|repeater| repeater.update(now))
+            .collect();
+        self.apply_changes(changes)
+    }
+
+    fn apply_changes(&mut self, changes: Vec<ActionChange>) -> Result<(), String> {
+        for (action, pressed) in changes {
+            self.handle_button(&action, pressed)?;
+        }
+        Ok(())
+    }
+
+    fn active_layer(&self) -> Option<&Layer> {
+        let name = self.active_layer.as_ref()?;
+        self.config.layers.get(name)
+    }
+
+    /// Name of the layer switched by this button, if it is a layer button.
+    fn layer_of_button(&self, btn: Button, code: u32) -> Option<String> {
+        self.config
+            .layers
+            .iter()
+            .find(|(_, layer)| button_names(btn, code).contains(&layer.button))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Handles a press or release of a layer button. Everything held is released when the
+    /// layer actually changes.
+    fn switch_layer(&mut self, name: String, pressed: bool) -> Result<(), String> {
+        let activation = self.config.layers[&name].activation;
+        let is_active = self.active_layer.as_ref() == Some(&name);
+        let next = match (activation, pressed) {
+            (LayerActivation::Hold, true) => Some(name),
+            (LayerActivation::Hold, false) if is_active => None,
+            (LayerActivation::Toggle, true) if is_active => None,
+            (LayerActivation::Toggle, true) => Some(name),
+            _ => return Ok(()),
+        };
+        if next != self.active_layer {
+            self.release_all()?;
+            self.active_layer = next;
+        }
+        Ok(())
+    }
+
+    /// Releases every action we are holding down, and forgets the gestures in progress.
+    fn release_all(&mut self) -> Result<(), String> {
+        let mut changes: Vec<ActionChange> = self
+            .held_buttons
+            .drain()
+            .map(|(_, action)| (action, false))
+            .collect();
+        for tracker in self.gesture_trackers.values_mut() {
+            changes.extend(tracker.cancel());
+        }
+        for (_, repeater) in self.repeaters.drain() {
+            changes.extend(repeater.release());
+        }
+        for (_, (_, action)) in self.axis_directions.drain() {
+            changes.extend(action.map(|action| (action, false)));
+        }
+        for chord in self.held_chords.drain(..) {
+            changes.extend(chord.action.map(|action| (action, false)));
+        }
+        self.pending_presses.clear();
+        // The state is already gone, one failing release must not leave the others stuck down.
+        let mut result = Ok(());
+        for (action, pressed) in changes {
+            if let Err(e) = self.handle_button(&action, pressed)
+                && result.is_ok()
+            {
+                result = Err(e);
             }
         }
+        result
+    }
+
+    /// Mapping of a button, the active layer first then the base one.
+    fn button_binding(&self, btn: Button, code: u32) -> Option<&ButtonBinding> {
+        self.active_layer()
+            .and_then(|layer| find_button(&layer.button_mapping, btn, code))
+            .or_else(|| find_button(&self.config.button_mapping, btn, code))
+    }
+
+    /// Presses and releases the actions of `axis_buttons` as the axis crosses its thresholds.
+    fn axis_buttons(&mut self, axis: Axis, code: u32, value: f32) -> Result<(), String> {
+        let find = |axis_buttons: &HashMap<String, AxisButtons>| {
+            [format!("{:?}", axis), code.to_string()]
+                .into_iter()
+                .find_map(|name| Some((name.clone(), axis_buttons.get(&name)?.clone())))
+        };
+        let Some((name, mapping)) = self
+            .active_layer()
+            .and_then(|layer| find(&layer.axis_buttons))
+            .or_else(|| find(&self.config.axis_buttons))
+        else {
+            return Ok(());
+        };
+        let direction = mapping.direction(value);
+        let previous = self.axis_directions.get(&name).map_or(0, |(previous, _)| *previous);
+        if direction == previous {
+            return Ok(());
+        }
+        if let Some((_, Some(action))) = self.axis_directions.remove(&name) {
+            self.handle_button(&action, false)?;
+        }
+        let action = mapping.action(direction).cloned();
+        if let Some(action) = &action {
+            self.handle_button(action, true)?;
+        }
+        self.axis_directions.insert(name, (direction, action));
         Ok(())
     }
 
-    fn smooth_axis_event_value(&self, axis: &gilrs::Axis, value: f32) -> Result<f32, &str> {
-        let profile = self.axis_profile(axis)?;
-        smooth_profile(value, profile)
+    /// Axis mapping table of the active layer, or the base one.
+    fn axis_mapping(&self) -> &[AxisMapping] {
+        match self.active_layer() {
+            Some(layer) if !layer.axis_mapping.is_empty() => &layer.axis_mapping,
+            _ => &self.config.axis_mapping,
+        }
     }
 
-    fn smooth_button_event_value(&self, btn: &gilrs::Button, value: f32) -> Result<f32, &str> {
-        let profile = self.button_profile(btn)?;
-        smooth_profile(value, profile)
+    fn set_axis_value(&mut self, axis: Axis, value: f32) {
+        let previous = self.axis_values.insert(axis, value).unwrap_or(0.0);
+        self.update_toggles(ModifierInput::Axis(axis), previous.abs(), value.abs());
     }
 
-    fn axis_profile(&self, axis: &gilrs::Axis) -> Result<&AccelerationProfile, &str> {
-        let profile = match axis {
+    /// Flips the toggle modifiers bound to `input` when it gets pressed past halfway.
+    fn update_toggles(&mut self, input: ModifierInput, previous: f32, value: f32) {
+        if previous >= TOGGLE_THRESHOLD || value < TOGGLE_THRESHOLD {
+            return;
+        }
+        let modifiers = self.config.modifiers.iter();
+        for (modifier, toggled) in modifiers.zip(&mut self.toggled_modifiers) {
+            if modifier.mode == ModifierMode::Toggle && modifier.input == input {
+                *toggled = !*toggled;
+            }
+        }
+    }
+
+    /// Product of the factors of the active modifiers, 1 when none is.
+    fn sensitivity_modifier(&self) -> Result<f32, &'static str> {
+        let mut result = 1.0;
+        let modifiers = self.config.modifiers.iter();
+        for (modifier, toggled) in modifiers.zip(&self.toggled_modifiers) {
+            let pressed = match modifier.mode {
+                ModifierMode::Hold => {
+                    smooth_profile(self.modifier_input_value(modifier.input), &modifier.curve)?
+                }
+                ModifierMode::Toggle if *toggled => 1.0,
+                ModifierMode::Toggle => 0.0,
+            };
+            result *= 1.0 + (modifier.factor - 1.0) * pressed;
+        }
+        Ok(result)
+    }
+
+    /// How far a modifier input is pressed, between 0 and 1.
+    fn modifier_input_value(&self, input: ModifierInput) -> f32 {
+        match input {
+            ModifierInput::Button(button) => {
+                self.button_values.get(&button).copied().unwrap_or(0.0)
+            }
+            ModifierInput::Axis(axis) => self.axis_value(axis).abs().min(1.0),
+        }
+    }
+
+    fn axis_profile(&self, axis: &gilrs::Axis) -> &AccelerationProfile {
+        match axis {
             gilrs::Axis::LeftStickX | gilrs::Axis::LeftStickY => {
-                Ok(&self.config.left_joystick_smoothing)
+                &self.config.left_joystick_smoothing
             }
             gilrs::Axis::RightStickX | gilrs::Axis::RightStickY => {
-                Ok(&self.config.right_joystick_smoothing)
+                &self.config.right_joystick_smoothing
             }
-            gilrs::Axis::LeftZ | gilrs::Axis::RightZ => Ok(&self.config.zaxis_smoothing),
-            _ => Err("No profile defined for provided axis"),
-        }?;
-        Ok(profile)
+            gilrs::Axis::LeftZ | gilrs::Axis::RightZ => &self.config.zaxis_smoothing,
+            _ => &self.config.default_axis_smoothing,
+        }
     }
 
-    fn button_profile(&self, btn: &gilrs::Button) -> Result<&AccelerationProfile, &str> {
-        let profile = if btn == &self.config.aim_button {
-            &self.config.zaxis_smoothing
+    fn handle_button(
+        &mut self,
+        action: &ButtonAction,
+        pressed: bool,
+    ) -> Result<(), String> {
+        let output = self.output.as_mut();
+        let result = if let Some(btn) = MouseButton::from_action(action) {
+            click_control(output, btn, pressed)
+        } else if let Some(key) = Key::from_action(action) {
+            key_control(output, key, pressed)
+        } else if let ButtonAction::Combo(combo) = action {
+            combo_control(output, combo, pressed)
         } else {
-            &AccelerationProfile::Linear
+            Ok(())
         };
-        Ok(profile)
+        result.map_err(|e| e.to_string())
+    }
+}
+
+/// Names a button can be mapped under, its gilrs name then its raw code.
+fn button_names(btn: Button, code: u32) -> Vec<String> {
+    match btn {
+        Button::Unknown => vec![code.to_string()],
+        _ => vec![format!("{:?}", btn), code.to_string()],
+    }
+}
+
+fn find_button<T>(mapping: &HashMap<String, T>, btn: Button, code: u32) -> Option<&T> {
+    button_names(btn, code)
+        .into_iter()
+        .find_map(|name| mapping.get(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AccelerationRamp, Chord, ScrollConfig, SensitivityModifier};
+    use crate::gesture::at;
+    use crate::output::{FailingSink, OutputEvent, RecordingSink};
+
+    /// Raw code of the South button, any value does as long as it is the same.
+    const SOUTH: u32 = 0x130;
+    const LEFT_TRIGGER2: u32 = 0x138;
+    const RIGHT_TRIGGER2: u32 = 0x139;
+    const EAST: u32 = 0x131;
+    const LEFT_THUMB: u32 = 0x13d;
+
+    fn handler(config: Config) -> (GamepadHandler, RecordingSink) {
+        let sink = RecordingSink::default();
+        let handler = GamepadHandler::with_output(config, None, Box::new(sink.clone()));
+        (handler, sink)
     }
 
-    fn handle_button(&self, action: &crate::config::ButtonAction, event: &EventType) {
-        match action {
-            crate::config::ButtonAction::MouseLeft
-            | crate::config::ButtonAction::MouseRight
-            | crate::config::ButtonAction::MouseMiddle => {
-                click_control(action, event);
+    /// 100 px/s and a linear stick, so a full push moves 10 pixels in a 100 ms frame.
+    fn linear_config() -> Config {
+        Config {
+            mouse_speed: 100.0,
+            left_joystick_smoothing: AccelerationProfile::Linear,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn stick_push_moves_the_pointer() {
+        let (mut handler, sink) = handler(linear_config());
+        let event = PadEvent::AxisChanged(Axis::LeftStickX, 1.0, 0);
+        handler.handle_event(event, at(0)).unwrap();
+        handler.update(Duration::from_millis(100), at(100)).unwrap();
+        assert_eq!(sink.take(), [OutputEvent::Move { dx: 10, dy: 0 }]);
+
+        // Up on the stick is up on the screen.
+        let event = PadEvent::AxisChanged(Axis::LeftStickX, 0.0, 0);
+        handler.handle_event(event, at(100)).unwrap();
+        let event = PadEvent::AxisChanged(Axis::LeftStickY, 1.0, 1);
+        handler.handle_event(event, at(100)).unwrap();
+        handler.update(Duration::from_millis(100), at(200)).unwrap();
+        assert_eq!(sink.take(), [OutputEvent::Move { dx: 0, dy: -10 }]);
+    }
+
+    #[test]
+    fn speed_does_not_depend_on_the_frequency() {
+        let distance = |frequency: u64| {
+            let (mut handler, sink) = handler(linear_config());
+            let event = PadEvent::AxisChanged(Axis::LeftStickX, 1.0, 0);
+            handler.handle_event(event, at(0)).unwrap();
+            let frame = Duration::from_millis(1000 / frequency);
+            for i in 1..=frequency {
+                handler.update(frame, at(i * 1000 / frequency)).unwrap();
             }
-            _ => {}
+            let moves = sink.take().into_iter().map(|event| match event {
+                OutputEvent::Move { dx, dy: 0 } => dx,
+                event => panic!("unexpected {:?}", event),
+            });
+            moves.sum::<i32>()
+        };
+        // A second of full push at 100 px/s.
+        assert_eq!(distance(50), 100);
+        assert_eq!(distance(500), 100);
+    }
+
+    #[test]
+    fn no_deadzone_still_moves() {
+        let config = Config {
+            joystick_deadzone: 0.0,
+            ..linear_config()
+        };
+        let (mut handler, sink) = handler(config);
+        // A frame with the stick centered first, it must not leave anything behind.
+        handler.update(Duration::from_millis(100), at(0)).unwrap();
+        let event = PadEvent::AxisChanged(Axis::LeftStickX, 1.0, 0);
+        handler.handle_event(event, at(0)).unwrap();
+        handler.update(Duration::from_millis(100), at(100)).unwrap();
+        assert_eq!(sink.take(), [OutputEvent::Move { dx: 10, dy: 0 }]);
+    }
+
+    #[test]
+    fn centered_stick_does_not_move() {
+        let (mut handler, sink) = handler(linear_config());
+        let event = PadEvent::AxisChanged(Axis::LeftStickX, 0.001, 0);
+        handler.handle_event(event, at(0)).unwrap();
+        handler.update(Duration::from_millis(100), at(100)).unwrap();
+        assert_eq!(sink.take(), []);
+    }
+
+    #[test]
+    fn mapped_stick_keeps_its_settings() {
+        let mut config = linear_config();
+        config.left_joystick.invert_x = true;
+        config.left_joystick.x_sensitivity = 2.0;
+        config.axis_mapping = vec![AxisMapping {
+            input: AxisInput::Axis(Axis::LeftStickX),
+            target: AxisTarget::PointerX,
+            scale: 1.0,
+            curve: None,
+            deadzone: 0.0,
+        }];
+        let (mut handler, sink) = handler(config);
+        let event = PadEvent::AxisChanged(Axis::LeftStickX, 0.001, 0);
+        handler.handle_event(event, at(0)).unwrap();
+        handler.update(Duration::from_millis(100), at(100)).unwrap();
+        assert_eq!(sink.take(), []);
+
+        let event = PadEvent::AxisChanged(Axis::LeftStickX, 1.0, 0);
+        handler.handle_event(event, at(100)).unwrap();
+        handler.update(Duration::from_millis(100), at(200)).unwrap();
+        assert_eq!(sink.take(), [OutputEvent::Move { dx: -20, dy: 0 }]);
+    }
+
+    #[test]
+    fn ramp_starts_over_once_the_stick_is_let_go() {
+        // 10 pixels per 62.5 ms frame at full push, up to 3 times that after 4 frames.
+        let config = Config {
+            mouse_speed: 160.0,
+            acceleration_ramp: Some(AccelerationRamp {
+                threshold: 0.5,
+                max_multiplier: 3.0,
+                duration: 0.25,
+            }),
+            ..linear_config()
+        };
+        let (mut handler, sink) = handler(config);
+        let frame = Duration::from_micros(62_500);
+        let push = |handler: &mut GamepadHandler, x: f32, ms: u64| {
+            let event = PadEvent::AxisChanged(Axis::LeftStickX, x, 0);
+            handler.handle_event(event, at(ms)).unwrap();
+            handler.update(frame, at(ms)).unwrap();
+            sink.take()
+        };
+        for (ms, dx) in [(0, 15), (63, 20), (125, 25), (188, 30), (250, 30)] {
+            assert_eq!(
+                push(&mut handler, 1.0, ms),
+                [OutputEvent::Move { dx, dy: 0 }]
+            );
+        }
+        assert_eq!(push(&mut handler, 0.0, 313), []);
+        assert_eq!(
+            push(&mut handler, 1.0, 375),
+            [OutputEvent::Move { dx: 15, dy: 0 }]
+        );
+    }
+
+    #[test]
+    fn fine_stick_ignores_the_modifiers() {
+        let config = Config {
+            fine_joystick_sensitivity: Some(20.0),
+            right_joystick_smoothing: AccelerationProfile::Linear,
+            scroll: ScrollConfig {
+                source: ScrollSource::Disabled,
+                ..ScrollConfig::default()
+            },
+            ..linear_config()
+        };
+        let (mut handler, sink) = handler(config);
+        let event = PadEvent::AxisChanged(Axis::RightStickX, 1.0, 3);
+        handler.handle_event(event, at(0)).unwrap();
+        handler.update(Duration::from_millis(100), at(100)).unwrap();
+        assert_eq!(sink.take(), [OutputEvent::Move { dx: 2, dy: 0 }]);
+
+        // The default modifier triples the speed of the main stick only.
+        let event = PadEvent::ButtonChanged(Button::LeftTrigger2, 1.0, LEFT_TRIGGER2);
+        handler.handle_event(event, at(100)).unwrap();
+        let event = PadEvent::AxisChanged(Axis::LeftStickX, 1.0, 0);
+        handler.handle_event(event, at(100)).unwrap();
+        handler.update(Duration::from_millis(100), at(200)).unwrap();
+        assert_eq!(sink.take(), [OutputEvent::Move { dx: 32, dy: 0 }]);
+    }
+
+    #[test]
+    fn toggle_and_hold_modifiers_multiply() {
+        let mut config = linear_config();
+        // Next to the default one tripling the speed with the left trigger.
+        config.modifiers.push(SensitivityModifier {
+            input: ModifierInput::Button(Button::LeftThumb),
+            factor: 0.5,
+            curve: AccelerationProfile::Linear,
+            mode: ModifierMode::Toggle,
+        });
+        let (mut handler, sink) = handler(config);
+        let event = PadEvent::AxisChanged(Axis::LeftStickX, 1.0, 0);
+        handler.handle_event(event, at(0)).unwrap();
+        let l3 = |value| PadEvent::ButtonChanged(Button::LeftThumb, value, LEFT_THUMB);
+        let lt = |value| PadEvent::ButtonChanged(Button::LeftTrigger2, value, LEFT_TRIGGER2);
+        let steps = [
+            (vec![lt(1.0)], 30),
+            // Clicked once, the toggle stays on after L3 goes back up.
+            (vec![l3(1.0), l3(0.0)], 15),
+            (vec![lt(0.0)], 5),
+            (vec![l3(1.0), l3(0.0)], 10),
+        ];
+        for (i, (events, dx)) in steps.into_iter().enumerate() {
+            let ms = 100 * i as u64;
+            for event in events {
+                handler.handle_event(event, at(ms)).unwrap();
+            }
+            handler.update(Duration::from_millis(100), at(ms)).unwrap();
+            assert_eq!(sink.take(), [OutputEvent::Move { dx, dy: 0 }], "step {}", i);
+        }
+    }
+
+    #[test]
+    fn right_trigger_scrolls_up() {
+        let config = Config {
+            scroll: ScrollConfig {
+                source: ScrollSource::Triggers,
+                sensitivity: 10.0,
+                smoothing: AccelerationProfile::Linear,
+                ..ScrollConfig::default()
+            },
+            ..linear_config()
+        };
+        let (mut handler, sink) = handler(config);
+        let event = PadEvent::ButtonChanged(Button::RightTrigger2, 1.0, RIGHT_TRIGGER2);
+        handler.handle_event(event, at(0)).unwrap();
+        handler.update(Duration::from_millis(100), at(100)).unwrap();
+        let wheel = |vertical| OutputEvent::Wheel {
+            vertical,
+            horizontal: 0,
+        };
+        assert_eq!(sink.take(), [wheel(1)]);
+
+        // Both pulled cancel out, the left one alone scrolls down.
+        let event = PadEvent::ButtonChanged(Button::LeftTrigger2, 1.0, LEFT_TRIGGER2);
+        handler.handle_event(event, at(100)).unwrap();
+        handler.update(Duration::from_millis(100), at(200)).unwrap();
+        assert_eq!(sink.take(), []);
+        let event = PadEvent::ButtonChanged(Button::RightTrigger2, 0.0, RIGHT_TRIGGER2);
+        handler.handle_event(event, at(200)).unwrap();
+        handler.update(Duration::from_millis(100), at(300)).unwrap();
+        assert_eq!(sink.take(), [wheel(-1)]);
+    }
+
+    #[test]
+    fn mapped_button_clicks() {
+        let (mut handler, sink) = handler(Config::default());
+        let press = PadEvent::ButtonPressed(Button::South, SOUTH);
+        handler.handle_event(press, at(0)).unwrap();
+        let release = PadEvent::ButtonReleased(Button::South, SOUTH);
+        handler.handle_event(release, at(50)).unwrap();
+        assert_eq!(
+            sink.take(),
+            [
+                OutputEvent::Button {
+                    button: MouseButton::Left,
+                    pressed: true
+                },
+                OutputEvent::Button {
+                    button: MouseButton::Left,
+                    pressed: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn layer_button_only_switches_the_layer() {
+        let mut config = linear_config();
+        let layer = Layer {
+            button: "LeftTrigger2".to_string(),
+            activation: LayerActivation::Hold,
+            button_mapping: HashMap::from([(
+                "South".to_string(),
+                ButtonAction::Key(Key::A).into(),
+            )]),
+            axis_mapping: Vec::new(),
+            axis_buttons: HashMap::new(),
+        };
+        config.layers.insert("edit".to_string(), layer);
+        let (mut handler, sink) = handler(config);
+        let events = [
+            PadEvent::ButtonChanged(Button::LeftTrigger2, 1.0, LEFT_TRIGGER2),
+            PadEvent::ButtonPressed(Button::LeftTrigger2, LEFT_TRIGGER2),
+            PadEvent::ButtonPressed(Button::South, SOUTH),
+            PadEvent::ButtonReleased(Button::South, SOUTH),
+            PadEvent::AxisChanged(Axis::LeftStickX, 1.0, 0),
+        ];
+        for event in events {
+            handler.handle_event(event, at(0)).unwrap();
+        }
+        handler.update(Duration::from_millis(100), at(100)).unwrap();
+        // The default modifier on the same trigger does not speed the cursor up.
+        assert_eq!(
+            sink.take(),
+            [
+                OutputEvent::Key {
+                    key: Key::A,
+                    pressed: true
+                },
+                OutputEvent::Key {
+                    key: Key::A,
+                    pressed: false
+                },
+                OutputEvent::Move { dx: 10, dy: 0 },
+            ]
+        );
+    }
+
+    /// South and East together press A, 50 ms apart at most.
+    fn chord_config(buttons: [&str; 2]) -> Config {
+        Config {
+            chords: vec![Chord {
+                buttons: buttons.map(String::from).to_vec(),
+                action: ButtonAction::Key(Key::A),
+            }],
+            chord_window: 0.05,
+            ..Config::default()
+        }
+    }
+
+    fn click(button: MouseButton, pressed: bool) -> OutputEvent {
+        OutputEvent::Button { button, pressed }
+    }
+
+    #[test]
+    fn chord_replaces_the_actions_of_its_buttons() {
+        let (mut handler, sink) = handler(chord_config(["South", "East"]));
+        let events = [
+            (PadEvent::ButtonPressed(Button::South, SOUTH), 0),
+            (PadEvent::ButtonPressed(Button::East, EAST), 30),
+            (PadEvent::ButtonReleased(Button::South, SOUTH), 200),
+            (PadEvent::ButtonReleased(Button::East, EAST), 220),
+        ];
+        for (event, millis) in events {
+            handler.handle_event(event, at(millis)).unwrap();
+            handler
+                .update(Duration::from_millis(10), at(millis))
+                .unwrap();
+        }
+        handler.update(Duration::from_millis(10), at(300)).unwrap();
+        assert_eq!(
+            sink.take(),
+            [
+                OutputEvent::Key {
+                    key: Key::A,
+                    pressed: true
+                },
+                OutputEvent::Key {
+                    key: Key::A,
+                    pressed: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_window_expires() {
+        let (mut handler, sink) = handler(chord_config(["South", "East"]));
+        let press = PadEvent::ButtonPressed(Button::South, SOUTH);
+        handler.handle_event(press, at(0)).unwrap();
+        handler.update(Duration::from_millis(40), at(40)).unwrap();
+        assert_eq!(sink.take(), []);
+        handler.update(Duration::from_millis(20), at(60)).unwrap();
+        assert_eq!(sink.take(), [click(MouseButton::Left, true)]);
+
+        // Too late for the chord, East waits for its own window then does its own action.
+        let press = PadEvent::ButtonPressed(Button::East, EAST);
+        handler.handle_event(press, at(80)).unwrap();
+        handler.update(Duration::from_millis(60), at(140)).unwrap();
+        assert_eq!(sink.take(), [click(MouseButton::Right, true)]);
+    }
+
+    #[test]
+    fn quick_release_is_still_a_click() {
+        let (mut handler, sink) = handler(chord_config(["South", "East"]));
+        let press = PadEvent::ButtonPressed(Button::South, SOUTH);
+        handler.handle_event(press, at(0)).unwrap();
+        let release = PadEvent::ButtonReleased(Button::South, SOUTH);
+        handler.handle_event(release, at(20)).unwrap();
+        assert_eq!(
+            sink.take(),
+            [
+                click(MouseButton::Left, true),
+                click(MouseButton::Left, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_needs_a_press_per_button() {
+        let code = SOUTH.to_string();
+        let (mut handler, sink) = handler(chord_config(["South", &code]));
+        let press = PadEvent::ButtonPressed(Button::South, SOUTH);
+        handler.handle_event(press, at(0)).unwrap();
+        handler.update(Duration::from_millis(100), at(100)).unwrap();
+        assert_eq!(sink.take(), [click(MouseButton::Left, true)]);
+    }
+
+    #[test]
+    fn failed_release_still_releases_the_rest() {
+        let mut config = Config::default();
+        for (button, key) in [("South", Key::A), ("North", Key::B), ("West", Key::C)] {
+            let binding = ButtonAction::Key(key).into();
+            config.button_mapping.insert(button.to_string(), binding);
+        }
+        let recording = RecordingSink::default();
+        let sink = FailingSink {
+            recording: recording.clone(),
+            key: Key::B,
+            pressed: false,
+        };
+        let mut handler = GamepadHandler::with_output(config, None, Box::new(sink));
+        let presses = [
+            PadEvent::ButtonPressed(Button::South, SOUTH),
+            PadEvent::ButtonPressed(Button::North, 0x133),
+            PadEvent::ButtonPressed(Button::West, 0x134),
+        ];
+        for event in presses {
+            handler.handle_event(event, at(0)).unwrap();
         }
+        recording.take();
+
+        assert!(handler.release_all().is_err());
+        let mut released = recording.take();
+        released.sort_by_key(|event| format!("{:?}", event));
+        let key_up = |key| OutputEvent::Key {
+            key,
+            pressed: false,
+        };
+        assert_eq!(released, [key_up(Key::A), key_up(Key::C)]);
+    }
+
+    #[test]
+    fn unmapped_button_does_nothing() {
+        let (mut handler, sink) = handler(Config::default());
+        let press = PadEvent::ButtonPressed(Button::North, 0x133);
+        handler.handle_event(press, at(0)).unwrap();
+        assert_eq!(sink.take(), []);
     }
 }
@@ -0,0 +1,300 @@
+use std::time::{Duration, Instant};
+
+use crate::config::{ButtonAction, ButtonGestures};
+
+/// Test time, `millis` after an arbitrary start shared by all tests.
+#[cfg(test)]
+pub fn at(millis: u64) -> Instant {
+    static START: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
+    *START.get_or_init(Instant::now) + Duration::from_millis(millis)
+}
+
+/// An action to press or release, as decided by a `GestureTracker`.
+pub type ActionChange = (ButtonAction, bool);
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Idle,
+    /// Down, too early to tell whether it is a tap or a hold.
+    Pressed { since: Instant },
+    /// Down past `hold_time`, the hold action (if any) is pressed.
+    Holding {
+        since: Instant,
+        long_pressed: bool,
+    },
+    /// Tapped once, waiting to see whether a second tap follows.
+    Released { at: Instant },
+    /// Down for the second time, the double tap action is pressed.
+    DoubleTapping,
+}
+
+/// Tells apart taps, holds, double taps and long presses of one button.
+/// Fed with the button presses and releases plus a regular `update` for the timeouts, it answers
+/// with the actions to press and release.
+#[derive(Debug, Clone)]
+pub struct GestureTracker {
+    gestures: ButtonGestures,
+    state: State,
+}
+
+impl GestureTracker {
+    pub fn new(gestures: ButtonGestures) -> Self {
+        Self {
+            gestures,
+            state: State::Idle,
+        }
+    }
+
+    pub fn press(&mut self, time: Instant) -> Vec<ActionChange> {
+        let gestures = &self.gestures;
+        let mut changes = Vec::new();
+        self.state = match (self.state, &gestures.double_tap) {
+            (State::Released { at }, Some(action))
+                if elapsed(at, time) < seconds(gestures.double_tap_window) =>
+            {
+                changes.push((action.clone(), true));
+                State::DoubleTapping
+            }
+            (State::Released { .. }, _) => {
+                // The window ended before `update` could tell, the first tap still counts.
+                changes.extend(click(&gestures.tap));
+                State::Pressed { since: time }
+            }
+            _ => State::Pressed { since: time },
+        };
+        changes
+    }
+
+    pub fn release(&mut self, time: Instant) -> Vec<ActionChange> {
+        let mut changes = Vec::new();
+        self.state = match self.state {
+            State::Pressed { .. } if self.gestures.double_tap.is_some() => {
+                State::Released { at: time }
+            }
+            State::Pressed { .. } => {
+                changes.extend(click(&self.gestures.tap));
+                State::Idle
+            }
+            State::Holding { .. } => {
+                changes.extend(self.gestures.hold.iter().map(|a| (a.clone(), false)));
+                State::Idle
+            }
+            State::DoubleTapping => {
+                changes.extend(self.gestures.double_tap.iter().map(|a| (a.clone(), false)));
+                State::Idle
+            }
+            state => state,
+        };
+        changes
+    }
+
+    /// Resolves what only time can tell: a press lasting long enough to be a hold, or a tap
+    /// that did not get its second one.
+    pub fn update(&mut self, now: Instant) -> Vec<ActionChange> {
+        let elapsed = |since: Instant| elapsed(since, now);
+        let gestures = &self.gestures;
+        let mut changes = Vec::new();
+        self.state = match self.state {
+            State::Pressed { since }
+                if elapsed(since) >= seconds(gestures.hold_time)
+                    && (gestures.hold.is_some() || gestures.long_press.is_some()) =>
+            {
+                changes.extend(gestures.hold.iter().map(|a| (a.clone(), true)));
+                State::Holding {
+                    since,
+                    long_pressed: false,
+                }
+            }
+            State::Holding {
+                since,
+                long_pressed: false,
+            } if elapsed(since) >= seconds(gestures.long_press_time) => {
+                changes.extend(click(&gestures.long_press));
+                State::Holding {
+                    since,
+                    long_pressed: true,
+                }
+            }
+            State::Released { at } if elapsed(at) >= seconds(gestures.double_tap_window) => {
+                changes.extend(click(&gestures.tap));
+                State::Idle
+            }
+            state => state,
+        };
+        changes
+    }
+
+    /// Forgets the gesture in progress, releasing whatever it holds.
+    pub fn cancel(&mut self) -> Vec<ActionChange> {
+        let held = match self.state {
+            State::Holding { .. } => &self.gestures.hold,
+            State::DoubleTapping => &self.gestures.double_tap,
+            _ => &None,
+        };
+        let changes = held.iter().map(|a| (a.clone(), false)).collect();
+        self.state = State::Idle;
+        changes
+    }
+
+    /// Whether nothing is pressed or pending, so the tracker can pick up a new configuration.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, State::Idle)
+    }
+
+    pub fn set_gestures(&mut self, gestures: ButtonGestures) {
+        self.gestures = gestures;
+    }
+}
+
+impl ButtonGestures {
+    pub fn validate(&self) -> Result<(), String> {
+        let timings = [
+            ("hold_time", self.hold_time),
+            ("long_press_time", self.long_press_time),
+            ("double_tap_window", self.double_tap_window),
+        ];
+        for (name, seconds) in timings {
+            // They end up in a `Duration`, which panics on what does not fit.
+            if !(seconds >= 0.0 && Duration::try_from_secs_f32(seconds).is_ok()) {
+                return Err(format!(
+                    "{} must be a number of seconds, 0 or more, got {}",
+                    name, seconds
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn seconds(duration: f32) -> Duration {
+    Duration::from_secs_f32(duration)
+}
+
+fn elapsed(since: Instant, now: Instant) -> Duration {
+    now.saturating_duration_since(since)
+}
+
+/// Press and release right away, for the gestures only known once they are over.
+fn click(action: &Option<ButtonAction>) -> Vec<ActionChange> {
+    match action {
+        Some(action) => vec![(action.clone(), true), (action.clone(), false)],
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::Key;
+
+    const TAP: ButtonAction = ButtonAction::Key(Key::A);
+    const HOLD: ButtonAction = ButtonAction::Key(Key::B);
+    const DOUBLE_TAP: ButtonAction = ButtonAction::Key(Key::C);
+    const LONG_PRESS: ButtonAction = ButtonAction::Key(Key::D);
+
+    /// Every gesture bound, with the default 250 ms hold, 1 s long press and 250 ms window.
+    fn tracker() -> GestureTracker {
+        GestureTracker::new(ButtonGestures {
+            tap: Some(TAP),
+            hold: Some(HOLD),
+            double_tap: Some(DOUBLE_TAP),
+            long_press: Some(LONG_PRESS),
+            hold_time: 0.25,
+            long_press_time: 1.0,
+            double_tap_window: 0.25,
+        })
+    }
+
+    fn clicked(action: ButtonAction) -> Vec<ActionChange> {
+        vec![(action.clone(), true), (action, false)]
+    }
+
+    #[test]
+    fn tap_waits_for_the_double_tap_window() {
+        let mut tracker = tracker();
+        assert_eq!(tracker.press(at(0)), []);
+        assert_eq!(tracker.release(at(50)), []);
+        assert_eq!(tracker.update(at(200)), []);
+        assert_eq!(tracker.update(at(300)), clicked(TAP));
+        assert!(tracker.is_idle());
+    }
+
+    #[test]
+    fn tap_without_double_tap_clicks_on_release() {
+        let mut tracker = GestureTracker::new(ButtonGestures {
+            double_tap: None,
+            ..tracker().gestures
+        });
+        assert_eq!(tracker.press(at(0)), []);
+        assert_eq!(tracker.release(at(50)), clicked(TAP));
+        assert!(tracker.is_idle());
+    }
+
+    #[test]
+    fn hold_is_pressed_until_release() {
+        let mut tracker = tracker();
+        tracker.press(at(0));
+        assert_eq!(tracker.update(at(200)), []);
+        assert_eq!(tracker.update(at(250)), [(HOLD, true)]);
+        assert_eq!(tracker.update(at(500)), []);
+        assert_eq!(tracker.release(at(600)), [(HOLD, false)]);
+    }
+
+    #[test]
+    fn long_press_clicks_once_on_top_of_hold() {
+        let mut tracker = tracker();
+        tracker.press(at(0));
+        assert_eq!(tracker.update(at(300)), [(HOLD, true)]);
+        assert_eq!(tracker.update(at(900)), []);
+        assert_eq!(tracker.update(at(1000)), clicked(LONG_PRESS));
+        assert_eq!(tracker.update(at(2000)), []);
+        assert_eq!(tracker.release(at(2100)), [(HOLD, false)]);
+    }
+
+    #[test]
+    fn double_tap_is_pressed_with_the_second_press() {
+        let mut tracker = tracker();
+        tracker.press(at(0));
+        tracker.release(at(50));
+        assert_eq!(tracker.press(at(200)), [(DOUBLE_TAP, true)]);
+        // Held, the second press does not turn into a hold.
+        assert_eq!(tracker.update(at(600)), []);
+        assert_eq!(tracker.release(at(700)), [(DOUBLE_TAP, false)]);
+        assert!(tracker.is_idle());
+    }
+
+    #[test]
+    fn press_after_the_window_is_a_new_press() {
+        let mut tracker = tracker();
+        tracker.press(at(0));
+        tracker.release(at(50));
+        // No `update` in between, the press itself has to notice the window is over.
+        assert_eq!(tracker.press(at(400)), clicked(TAP));
+        assert_eq!(tracker.release(at(450)), []);
+        assert_eq!(tracker.update(at(700)), clicked(TAP));
+    }
+
+    #[test]
+    fn validate_rejects_bad_timings() {
+        assert!(tracker().gestures.validate().is_ok());
+        for seconds in [-0.1, f32::NAN, f32::INFINITY, 1e30] {
+            let gestures = [
+                ButtonGestures {
+                    hold_time: seconds,
+                    ..tracker().gestures
+                },
+                ButtonGestures {
+                    long_press_time: seconds,
+                    ..tracker().gestures
+                },
+                ButtonGestures {
+                    double_tap_window: seconds,
+                    ..tracker().gestures
+                },
+            ];
+            for gestures in gestures {
+                assert!(gestures.validate().is_err(), "{:?}", gestures);
+            }
+        }
+    }
+}
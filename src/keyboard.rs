@@ -0,0 +1,88 @@
+use crate::keys::{Key, KeyCombo};
+use crate::output::OutputSink;
+
+pub fn key_control(
+    output: &mut dyn OutputSink,
+    key: Key,
+    pressed: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    output.key(key, pressed)
+}
+
+/// Presses the keys of the combo in order, and releases them in reverse order so the
+/// modifiers are still held when the main key goes up.
+pub fn combo_control(
+    output: &mut dyn OutputSink,
+    combo: &KeyCombo,
+    pressed: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if pressed {
+        for (i, key) in combo.keys.iter().enumerate() {
+            if let Err(e) = output.key(*key, true) {
+                // Otherwise the modifiers already down would stay stuck.
+                for key in combo.keys[..i].iter().rev() {
+                    let _ = output.key(*key, false);
+                }
+                return Err(e);
+            }
+        }
+    } else {
+        for key in combo.keys.iter().rev() {
+            output.key(*key, false)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{FailingSink, OutputEvent, RecordingSink};
+
+    fn combo(text: &str) -> KeyCombo {
+        KeyCombo::try_from(text.to_string()).unwrap()
+    }
+
+    fn key_event(key: Key, pressed: bool) -> OutputEvent {
+        OutputEvent::Key { key, pressed }
+    }
+
+    #[test]
+    fn combo_presses_in_order_and_releases_in_reverse() {
+        let mut sink = RecordingSink::default();
+        let combo = combo("ctrl+shift+t");
+        combo_control(&mut sink, &combo, true).unwrap();
+        combo_control(&mut sink, &combo, false).unwrap();
+        assert_eq!(
+            sink.take(),
+            [
+                key_event(Key::Ctrl, true),
+                key_event(Key::Shift, true),
+                key_event(Key::T, true),
+                key_event(Key::T, false),
+                key_event(Key::Shift, false),
+                key_event(Key::Ctrl, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_press_releases_the_keys_already_down() {
+        let recording = RecordingSink::default();
+        let mut sink = FailingSink {
+            recording: recording.clone(),
+            key: Key::T,
+            pressed: true,
+        };
+        assert!(combo_control(&mut sink, &combo("ctrl+shift+t"), true).is_err());
+        assert_eq!(
+            recording.take(),
+            [
+                key_event(Key::Ctrl, true),
+                key_event(Key::Shift, true),
+                key_event(Key::Shift, false),
+                key_event(Key::Ctrl, false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::config::ButtonAction;
+
+// Declares the enum along with the list of all its variants, backends like uinput need to
+// announce every key they may press up front.
+macro_rules! keys {
+    ($($key:ident),* $(,)?) => {
+        /// Keyboard keys we know how to press on every backend.
+        /// Written in the config by name, for instance `{ Key = "Enter" }`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub enum Key {
+            $($key),*
+        }
+
+        impl Key {
+            pub const ALL: &[Key] = &[$(Key::$key),*];
+        }
+    };
+}
+
+keys! {
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Enter, Escape, Tab, Space, Backspace, Delete, Insert,
+    Home, End, PageUp, PageDown,
+    Left, Right, Up, Down,
+    Ctrl, Shift, Alt, Super,
+}
+
+impl Key {
+    /// The key behind an action, if the action presses a key at all.
+    pub fn from_action(action: &ButtonAction) -> Option<Self> {
+        match action {
+            ButtonAction::Key(key) => Some(*key),
+            ButtonAction::LeftArrow => Some(Self::Left),
+            ButtonAction::RightArrow => Some(Self::Right),
+            ButtonAction::UpArrow => Some(Self::Up),
+            ButtonAction::DownArrow => Some(Self::Down),
+            _ => None,
+        }
+    }
+
+    /// Parses a key name the way people write shortcuts, case insensitive.
+    /// Accepts the enum names ("PageUp", "F5", "Num1") plus the usual short forms ("1", "esc", "left").
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        let alias = match name.as_str() {
+            "esc" => Some(Self::Escape),
+            "return" => Some(Self::Enter),
+            "del" => Some(Self::Delete),
+            "ins" => Some(Self::Insert),
+            "pgup" => Some(Self::PageUp),
+            "pgdown" | "pgdn" => Some(Self::PageDown),
+            "left" => Some(Self::Left),
+            "right" => Some(Self::Right),
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            "control" => Some(Self::Ctrl),
+            "win" | "meta" | "cmd" => Some(Self::Super),
+            _ => None,
+        };
+        alias.or_else(|| {
+            Self::ALL.iter().copied().find(|key| {
+                let ascii = key
+                    .ascii()
+                    .map(|c| (c as char).to_ascii_lowercase().to_string());
+                ascii.as_deref() == Some(name.as_str())
+                    || format!("{:?}", key).eq_ignore_ascii_case(&name)
+            })
+        })
+    }
+
+    pub fn is_modifier(self) -> bool {
+        matches!(self, Self::Ctrl | Self::Shift | Self::Alt | Self::Super)
+    }
+
+    /// Letters and digits as their uppercase ASCII character, None for the other keys.
+    pub fn ascii(self) -> Option<u8> {
+        let c = match self {
+            Key::A => b'A',
+            Key::B => b'B',
+            Key::C => b'C',
+            Key::D => b'D',
+            Key::E => b'E',
+            Key::F => b'F',
+            Key::G => b'G',
+            Key::H => b'H',
+            Key::I => b'I',
+            Key::J => b'J',
+            Key::K => b'K',
+            Key::L => b'L',
+            Key::M => b'M',
+            Key::N => b'N',
+            Key::O => b'O',
+            Key::P => b'P',
+            Key::Q => b'Q',
+            Key::R => b'R',
+            Key::S => b'S',
+            Key::T => b'T',
+            Key::U => b'U',
+            Key::V => b'V',
+            Key::W => b'W',
+            Key::X => b'X',
+            Key::Y => b'Y',
+            Key::Z => b'Z',
+            Key::Num0 => b'0',
+            Key::Num1 => b'1',
+            Key::Num2 => b'2',
+            Key::Num3 => b'3',
+            Key::Num4 => b'4',
+            Key::Num5 => b'5',
+            Key::Num6 => b'6',
+            Key::Num7 => b'7',
+            Key::Num8 => b'8',
+            Key::Num9 => b'9',
+            _ => return None,
+        };
+        Some(c)
+    }
+}
+
+/// Keys pressed together, like a keyboard shortcut.
+/// Written in the config as a readable string, for instance `{ Combo = "ctrl+shift+t" }`.
+/// Keys are pressed in the written order and released the other way around.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyCombo {
+    pub keys: Vec<Key>,
+}
+
+impl TryFrom<String> for KeyCombo {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let keys = value
+            .split('+')
+            .map(|name| Key::from_name(name).ok_or_else(|| format!("Unknown key {:?}", name)))
+            .collect::<Result<Vec<Key>, String>>()?;
+        // A modifier after the main key would be pressed too late to change it.
+        if let Some(last_modifier) = keys.iter().rposition(|key| key.is_modifier())
+            && keys[..last_modifier].iter().any(|key| !key.is_modifier())
+        {
+            return Err(format!("Modifiers must come first in {:?}", value));
+        }
+        Ok(Self { keys })
+    }
+}
+
+impl From<KeyCombo> for String {
+    fn from(combo: KeyCombo) -> Self {
+        combo.to_string()
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .keys
+            .iter()
+            .map(|key| format!("{:?}", key).to_ascii_lowercase())
+            .collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<KeyCombo, String> {
+        KeyCombo::try_from(text.to_string())
+    }
+
+    #[test]
+    fn parses_names_in_any_case() {
+        let combo = parse("Ctrl+SHIFT+t").unwrap();
+        assert_eq!(combo.keys, [Key::Ctrl, Key::Shift, Key::T]);
+        let combo = parse(" alt + PageUp ").unwrap();
+        assert_eq!(combo.keys, [Key::Alt, Key::PageUp]);
+    }
+
+    #[test]
+    fn parses_short_forms() {
+        let combo = parse("control+esc").unwrap();
+        assert_eq!(combo.keys, [Key::Ctrl, Key::Escape]);
+        assert_eq!(parse("win+1").unwrap().keys, [Key::Super, Key::Num1]);
+        assert_eq!(parse("left").unwrap().keys, [Key::Left]);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(parse("ctrl+banana").is_err());
+        assert!(parse("f13").is_err());
+    }
+
+    #[test]
+    fn rejects_empty_segments() {
+        assert!(parse("").is_err());
+        assert!(parse("ctrl++t").is_err());
+        assert!(parse("ctrl+").is_err());
+    }
+
+    #[test]
+    fn rejects_modifiers_after_the_key() {
+        assert!(parse("t+ctrl").is_err());
+        assert!(parse("ctrl+t+shift").is_err());
+    }
+
+    #[test]
+    fn round_trips_through_strings() {
+        let combo = parse("ctrl+shift+t").unwrap();
+        assert_eq!(String::from(combo.clone()), "ctrl+shift+t");
+        for key in Key::ALL {
+            let combo = KeyCombo { keys: vec![*key] };
+            assert_eq!(parse(&String::from(combo.clone())), Ok(combo));
+        }
+    }
+}
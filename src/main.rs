@@ -1,11 +1,25 @@
 mod app;
 mod config;
+mod deadzone;
+mod filter;
 mod gamepad;
+mod gesture;
+mod keyboard;
+mod keys;
 mod menu;
 mod mouse;
+mod output;
+mod repeat;
 mod setupapp;
 mod smoothing;
 mod tray;
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "windows")]
+mod win32;
+mod worker;
+#[cfg(target_os = "linux")]
+mod xtest;
 
 use app::StickApp;
 use config::Config;
@@ -1,103 +1,159 @@
+use std::time::Duration;
+
+use crate::output::{MouseButton, OutputSink};
+
 /// Mouse movement input structure
 #[derive(Debug)]
 pub struct MouseMovementInput {
-    /// Horizontal and vertical movement vector
+    /// Horizontal and vertical speed in pixels per second, every stick and speed setting
+    /// already applied
     pub movement_vector: [f32; 2],
-    /// Sensitivity factor for mouse movement
+    /// Part of a pixel not moved yet, carried over to the next frames.
+    pub remainder: [f32; 2],
+}
+
+/// Scroll wheel input structure
+#[derive(Debug, Default)]
+pub struct ScrollInput {
+    /// Horizontal and vertical scroll vector, positive goes right and up
+    pub scroll_vector: [f32; 2],
+    /// Notches per second at full deflection
     pub sensitivity_factor: f32,
-    /// We will ignore moves that dont reach that threshold.
-    pub deadzone: f32,
+    /// Part of a notch not sent yet, carried over to the next frames.
+    pub remainder: [f32; 2],
 }
 
-pub fn click_control(btn: &crate::config::ButtonAction, event: &gilrs::EventType) {
-    #[cfg(target_os = "linux")]
-    {
-        todo!("Linux not supported")
-    }
+pub fn click_control(
+    output: &mut dyn OutputSink,
+    btn: MouseButton,
+    pressed: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    output.button(btn, pressed)
+}
 
-    #[cfg(target_os = "windows")]
-    {
-        use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
-            mouse_event, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN,
-            MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP,
-        };
-        let [down, up] = match btn {
-            &crate::config::ButtonAction::MouseLeft => [MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP],
-            &crate::config::ButtonAction::MouseMiddle => {
-                [MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP]
-            }
-            &crate::config::ButtonAction::MouseRight => {
-                [MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP]
-            }
-            _ => [MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP], // Defaulting to left click atm
-        };
-        let action = match event {
-            gilrs::EventType::ButtonPressed(_, _) => down,
-            _ => up,
-        };
-        unsafe {
-            // the 0 is cause we dont use the wheel
-            mouse_event(action, 0, 0, 0, 0);
-        }
+/// Adds this frame's delta to what was left over and takes out the whole units.
+/// Since we cant move less than a pixel (or scroll less than a notch), slow speeds end up as
+/// one unit every N frames, which averages out to exactly the requested speed.
+fn take_whole_units(remainder: &mut [f32; 2], delta: [f32; 2]) -> [i32; 2] {
+    let mut whole_units = [0; 2];
+    for ((units, remainder), delta) in whole_units.iter_mut().zip(remainder).zip(delta) {
+        *remainder += delta;
+        let whole = remainder.trunc();
+        *remainder -= whole;
+        *units = whole as i32;
     }
+    whole_units
 }
 
 /// Handles mouse movement based on input
-pub fn movement_control(input: &MouseMovementInput) {
-    let sensitivity = input.sensitivity_factor;
-    let [mut horizontal, mut vertical] = input.movement_vector;
-    if horizontal.abs() < input.deadzone && vertical.abs() < input.deadzone {
-        return;
+pub fn movement_control(
+    output: &mut dyn OutputSink,
+    input: &mut MouseMovementInput,
+    elapsed: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let seconds = elapsed.as_secs_f32();
+    let [horizontal, vertical] = input.movement_vector;
+    if horizontal == 0.0 && vertical == 0.0 {
+        // Otherwise leftovers would move a bit on the next push.
+        input.remainder = [0.0, 0.0];
+        return Ok(());
     }
-    // We cleanup residual stuff
-    horizontal = if horizontal.abs() < input.deadzone {
-        0.0
-    } else {
-        horizontal
-    };
-    vertical = if vertical.abs() < input.deadzone {
-        0.0
-    } else {
-        // Lol might be overkill to put the else statement but eh
-        vertical
-    };
 
-    // For a big enough frequency the randomness will be smoothed out.
-    // The goal is that since we cant move less than a pixel, when we want to be
-    // slower than a pixel per period, we will just do one every N period, randomly but
-    // generally converging to the expected speed.
-    // the Y axis is inverted when sent to screen.
-    let lucky_num: f32 = rand::random();
-    let mut dxf = horizontal * sensitivity;
-    if dxf.abs() < 1. && dxf.abs() < lucky_num {
-        dxf = 0.;
-    } else if dxf.abs() < 1. && dxf.abs() >= lucky_num {
-        dxf = dxf.signum();
-    }
-    let delta_x = dxf as i32;
+    // Sticks count up as positive while the screen Y axis goes down, this is not the
+    // `invert_y` setting.
     // If you wanna buffer overflow its due to your config.
-    let mut dyf = -vertical * sensitivity;
-    if dyf.abs() < 1. && dyf.abs() < lucky_num {
-        dyf = 0.;
-    } else if dyf.abs() < 1. && dyf.abs() >= lucky_num {
-        dyf = dyf.signum();
+    let [delta_x, delta_y] = take_whole_units(
+        &mut input.remainder,
+        [horizontal * seconds, -vertical * seconds],
+    );
+
+    if delta_x != 0 || delta_y != 0 {
+        output.move_relative(delta_x, delta_y)?;
     }
-    let delta_y = dyf as i32;
+    Ok(())
+}
 
-    print!(
-        "x {} dx {} y {} dy {}  sensitivity {}  lucky_num {}              \r",
-        horizontal, delta_x, vertical, delta_y, sensitivity, lucky_num
+/// Handles the scroll wheel, sending whole notches and keeping the rest for later frames.
+pub fn scroll_control(
+    output: &mut dyn OutputSink,
+    input: &mut ScrollInput,
+    elapsed: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if input.scroll_vector == [0.0, 0.0] {
+        // Otherwise leftovers would scroll a bit on the next push.
+        input.remainder = [0.0, 0.0];
+        return Ok(());
+    }
+    let [horizontal, vertical] = take_whole_units(
+        &mut input.remainder,
+        input
+            .scroll_vector
+            .map(|v| v * input.sensitivity_factor * elapsed.as_secs_f32()),
     );
-    if delta_x != 0 || delta_y != 0 {
-        #[cfg(target_os = "windows")]
-        {
-            use windows_sys::Win32::UI::Input::KeyboardAndMouse::{mouse_event, MOUSEEVENTF_MOVE};
+    if horizontal != 0 || vertical != 0 {
+        output.wheel(vertical, horizontal)?;
+    }
+    Ok(())
+}
 
-            unsafe {
-                // the 0 is cause we dont use the wheel
-                mouse_event(MOUSEEVENTF_MOVE, delta_x, delta_y, 0, 0);
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{OutputEvent, RecordingSink};
+
+    #[test]
+    fn sub_pixel_deltas_carry_over_frames() {
+        let mut remainder = [0.0; 2];
+        let moved: Vec<_> = (0..5)
+            .map(|_| take_whole_units(&mut remainder, [0.4, 0.25]))
+            .collect();
+        assert_eq!(moved, [[0, 0], [0, 0], [1, 0], [0, 1], [1, 0]]);
+        assert!((remainder[0] - 0.0).abs() < 1e-5);
+        assert!((remainder[1] - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn negative_deltas_carry_over_too() {
+        let mut remainder = [0.0; 2];
+        assert_eq!(take_whole_units(&mut remainder, [-0.6, -2.5]), [0, -2]);
+        assert_eq!(take_whole_units(&mut remainder, [-0.6, -2.5]), [-1, -3]);
+        assert!((remainder[0] + 0.2).abs() < 1e-5);
+        assert!(remainder[1].abs() < 1e-5);
+    }
+
+    #[test]
+    fn remainder_stays_below_one_unit() {
+        let mut remainder = [0.0; 2];
+        for delta in [0.7, 3.9, -0.3, -5.8, 0.99, 12.5, -0.01] {
+            take_whole_units(&mut remainder, [delta, -delta]);
+            assert!(remainder.iter().all(|r| r.abs() < 1.0), "{:?}", remainder);
         }
-        // I dont want to support linux yet as i am manually testing for the moment on windows.
+    }
+
+    #[test]
+    fn slow_scrolling_adds_up_to_notches() {
+        let mut sink = RecordingSink::default();
+        let mut input = ScrollInput {
+            scroll_vector: [0.0, 0.5],
+            sensitivity_factor: 10.0,
+            remainder: [0.0, 0.0],
+        };
+        // A quarter notch per frame.
+        let frame = Duration::from_millis(50);
+        for _ in 0..8 {
+            scroll_control(&mut sink, &mut input, frame).unwrap();
+        }
+        let notch = OutputEvent::Wheel {
+            vertical: 1,
+            horizontal: 0,
+        };
+        assert_eq!(sink.take(), [notch.clone(), notch]);
+
+        // Letting go drops what was left.
+        scroll_control(&mut sink, &mut input, frame).unwrap();
+        input.scroll_vector = [0.0, 0.0];
+        scroll_control(&mut sink, &mut input, frame).unwrap();
+        assert_eq!(input.remainder, [0.0, 0.0]);
+        assert_eq!(sink.take(), []);
     }
 }
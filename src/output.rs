@@ -0,0 +1,164 @@
+use crate::config::{ButtonAction, OutputBackend};
+use crate::keys::Key;
+#[cfg(test)]
+use std::{cell::RefCell, rc::Rc};
+
+/// Mouse buttons an output sink can press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    /// The mouse button behind an action, if the action is a click at all.
+    pub fn from_action(action: &ButtonAction) -> Option<Self> {
+        match action {
+            ButtonAction::MouseLeft => Some(Self::Left),
+            ButtonAction::MouseRight => Some(Self::Right),
+            ButtonAction::MouseMiddle => Some(Self::Middle),
+            _ => None,
+        }
+    }
+}
+
+pub type OutputResult = Result<(), Box<dyn std::error::Error>>;
+
+/// Whatever ends up receiving the pointer and keyboard events we generate.
+/// `GamepadHandler` only talks to this trait, the OS specific code lives in the implementations.
+pub trait OutputSink {
+    /// Moves the pointer by the given amount of pixels, y going down like on screen.
+    fn move_relative(&mut self, dx: i32, dy: i32) -> OutputResult;
+    fn button(&mut self, btn: MouseButton, pressed: bool) -> OutputResult;
+    /// Scrolls by whole notches, positive values go up and right.
+    fn wheel(&mut self, vertical: i32, horizontal: i32) -> OutputResult;
+    fn key(&mut self, key: Key, pressed: bool) -> OutputResult;
+    /// Where the events end up, shown in the status.
+    fn describe(&self) -> String;
+    /// Called once at the end of every frame, for the sinks batching their requests.
+    fn flush(&mut self) -> OutputResult {
+        Ok(())
+    }
+}
+
+/// One call made on an output sink, as seen by the `RecordingSink`.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputEvent {
+    Move { dx: i32, dy: i32 },
+    Button { button: MouseButton, pressed: bool },
+    Wheel { vertical: i32, horizontal: i32 },
+    Key { key: Key, pressed: bool },
+}
+
+/// Sink that only remembers what it was asked to do, nothing reaches the OS.
+/// Meant for checking exactly which events a given input produces, the app itself never records.
+/// Clones share their events, so a test can keep one while the handler owns the other.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct RecordingSink {
+    events: Rc<RefCell<Vec<OutputEvent>>>,
+}
+
+#[cfg(test)]
+impl RecordingSink {
+    /// Events recorded since the last call.
+    pub fn take(&self) -> Vec<OutputEvent> {
+        self.events.take()
+    }
+}
+
+#[cfg(test)]
+impl OutputSink for RecordingSink {
+    fn move_relative(&mut self, dx: i32, dy: i32) -> OutputResult {
+        self.events.borrow_mut().push(OutputEvent::Move { dx, dy });
+        Ok(())
+    }
+
+    fn button(&mut self, button: MouseButton, pressed: bool) -> OutputResult {
+        self.events
+            .borrow_mut()
+            .push(OutputEvent::Button { button, pressed });
+        Ok(())
+    }
+
+    fn wheel(&mut self, vertical: i32, horizontal: i32) -> OutputResult {
+        self.events.borrow_mut().push(OutputEvent::Wheel {
+            vertical,
+            horizontal,
+        });
+        Ok(())
+    }
+
+    fn key(&mut self, key: Key, pressed: bool) -> OutputResult {
+        self.events
+            .borrow_mut()
+            .push(OutputEvent::Key { key, pressed });
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        String::from("Recording")
+    }
+}
+
+/// Records like `RecordingSink`, but fails to press or release (depending on `pressed`) `key`.
+#[cfg(test)]
+pub struct FailingSink {
+    pub recording: RecordingSink,
+    pub key: Key,
+    pub pressed: bool,
+}
+
+#[cfg(test)]
+impl OutputSink for FailingSink {
+    fn move_relative(&mut self, dx: i32, dy: i32) -> OutputResult {
+        self.recording.move_relative(dx, dy)
+    }
+
+    fn button(&mut self, button: MouseButton, pressed: bool) -> OutputResult {
+        self.recording.button(button, pressed)
+    }
+
+    fn wheel(&mut self, vertical: i32, horizontal: i32) -> OutputResult {
+        self.recording.wheel(vertical, horizontal)
+    }
+
+    fn key(&mut self, key: Key, pressed: bool) -> OutputResult {
+        if key == self.key && pressed == self.pressed {
+            return Err(format!("Cannot send {:?}", key).into());
+        }
+        self.recording.key(key, pressed)
+    }
+
+    fn describe(&self) -> String {
+        String::from("Failing")
+    }
+}
+
+/// Creates the sink matching the platform we are running on.
+/// The backend is only looked at on Linux where we have a choice.
+pub fn platform_sink(
+    backend: OutputBackend,
+) -> Result<Box<dyn OutputSink>, Box<dyn std::error::Error>> {
+    #[cfg(target_os = "linux")]
+    {
+        match backend {
+            OutputBackend::Uinput => Ok(Box::new(crate::uinput::VirtualPointer::new()?)),
+            OutputBackend::XTest => Ok(Box::new(crate::xtest::XTestSink::new()?)),
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let _ = backend;
+        Ok(Box::new(crate::win32::Win32Sink))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        let _ = backend;
+        Err("No output backend for this platform".into())
+    }
+}
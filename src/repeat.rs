@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+use crate::config::RepeatBinding;
+use crate::gesture::ActionChange;
+
+/// Repeats the action of a held button, see `RepeatBinding`.
+#[derive(Debug, Clone)]
+pub struct Repeater {
+    binding: RepeatBinding,
+    /// When the next press or release is due
+    next: Instant,
+    /// Whether the action is currently down
+    pressed: bool,
+}
+
+impl Repeater {
+    /// Starts repeating a button pressed at `time`, the action itself is pressed by the caller.
+    pub fn new(binding: RepeatBinding, time: Instant) -> Self {
+        let delay = Duration::from_secs_f32(binding.delay);
+        let next = time + delay.max(step(&binding));
+        Self {
+            binding,
+            next,
+            pressed: true,
+        }
+    }
+
+    /// Presses and releases due since the last update, driven by the frame loop so it behaves
+    /// the same on every backend.
+    pub fn update(&mut self, now: Instant) -> Vec<ActionChange> {
+        let mut changes = Vec::new();
+        if now < self.next {
+            return changes;
+        }
+        let action = &self.binding.action;
+        if self.binding.turbo {
+            self.pressed = !self.pressed;
+            changes.push((action.clone(), self.pressed));
+        } else {
+            changes.push((action.clone(), false));
+            changes.push((action.clone(), true));
+        }
+        self.next += step(&self.binding);
+        if self.next < now {
+            // We stalled, repeating everything we missed at once would be a burst.
+            self.next = now + step(&self.binding);
+        }
+        changes
+    }
+
+    /// What to send when the button goes up.
+    pub fn release(self) -> Vec<ActionChange> {
+        if self.pressed {
+            vec![(self.binding.action, false)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl RepeatBinding {
+    pub fn validate(&self) -> Result<(), String> {
+        // Both end up in a `Duration`, which panics on what does not fit.
+        let fits = |seconds: f32| Duration::try_from_secs_f32(seconds).is_ok();
+        if !(self.rate.is_finite() && self.rate > 0.0 && fits(1.0 / self.rate)) {
+            return Err(format!("rate must be a positive number, got {}", self.rate));
+        }
+        if !(self.delay.is_finite() && self.delay >= 0.0 && fits(self.delay)) {
+            return Err(format!(
+                "delay must be a number of seconds, 0 or more, got {}",
+                self.delay
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Time between two repeats, or between a press and a release in turbo mode.
+fn step(binding: &RepeatBinding) -> Duration {
+    let period = 1.0 / binding.rate;
+    let step = if binding.turbo { period / 2.0 } else { period };
+    Duration::from_secs_f32(step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ButtonAction;
+    use crate::gesture::at;
+
+    const ACTION: ButtonAction = ButtonAction::MouseLeft;
+
+    /// Every 250 ms once held for 500 ms, both exact in binary so no rounding gets in the way.
+    fn binding(turbo: bool) -> RepeatBinding {
+        RepeatBinding {
+            action: ACTION,
+            delay: 0.5,
+            rate: 4.0,
+            turbo,
+        }
+    }
+
+    fn repeated() -> Vec<ActionChange> {
+        vec![(ACTION, false), (ACTION, true)]
+    }
+
+    #[test]
+    fn waits_for_the_delay() {
+        let mut repeater = Repeater::new(binding(false), at(0));
+        assert_eq!(repeater.update(at(100)), []);
+        assert_eq!(repeater.update(at(499)), []);
+        assert_eq!(repeater.update(at(500)), repeated());
+    }
+
+    #[test]
+    fn repeats_at_the_rate() {
+        let mut repeater = Repeater::new(binding(false), at(0));
+        assert_eq!(repeater.update(at(500)), repeated());
+        assert_eq!(repeater.update(at(600)), []);
+        assert_eq!(repeater.update(at(750)), repeated());
+        assert_eq!(repeater.update(at(1000)), repeated());
+        assert_eq!(repeater.release(), [(ACTION, false)]);
+    }
+
+    #[test]
+    fn stall_does_not_burst() {
+        let mut repeater = Repeater::new(binding(false), at(0));
+        assert_eq!(repeater.update(at(2000)), repeated());
+        assert_eq!(repeater.update(at(2100)), []);
+        assert_eq!(repeater.update(at(2250)), repeated());
+    }
+
+    #[test]
+    fn turbo_toggles_twice_per_repeat() {
+        let mut repeater = Repeater::new(binding(true), at(0));
+        assert_eq!(repeater.update(at(499)), []);
+        assert_eq!(repeater.update(at(500)), [(ACTION, false)]);
+        assert_eq!(repeater.update(at(624)), []);
+        assert_eq!(repeater.update(at(625)), [(ACTION, true)]);
+        assert_eq!(repeater.update(at(750)), [(ACTION, false)]);
+        // Already up, nothing left to release.
+        assert_eq!(repeater.release(), []);
+    }
+
+    #[test]
+    fn validate_rejects_bad_timings() {
+        assert!(binding(false).validate().is_ok());
+        let with = |rate: f32, delay: f32| RepeatBinding {
+            rate,
+            delay,
+            ..binding(false)
+        };
+        assert!(with(10.0, 0.0).validate().is_ok());
+        for rate in [0.0, -5.0, f32::NAN, f32::INFINITY, 1e-30] {
+            assert!(with(rate, 0.4).validate().is_err(), "rate {}", rate);
+        }
+        for delay in [-0.1, f32::NAN, f32::INFINITY] {
+            assert!(with(10.0, delay).validate().is_err(), "delay {}", delay);
+        }
+    }
+}
@@ -1,9 +1,16 @@
 use std::f32::consts::PI;
 
-use crate::config::AccelerationProfile;
+use crate::config::{AccelerationProfile, CurveMode, CustomCurve};
 
-pub fn smooth_profile(value: f32, profile: &AccelerationProfile) -> Result<f32, &str> {
+pub fn smooth_profile(value: f32, profile: &AccelerationProfile) -> Result<f32, &'static str> {
     let smoothing_function: fn(f32) -> f32 = match profile {
+        // Those two carry parameters so they cannot be plain functions.
+        AccelerationProfile::Power(exponent) => {
+            return Ok(value.signum() * value.abs().powf(*exponent));
+        }
+        AccelerationProfile::Custom(curve) => {
+            return Ok(value.signum() * curve.evaluate(value.abs().min(1.0)));
+        }
         AccelerationProfile::Linear => |x| x,
         AccelerationProfile::SmoothStep => |x| x * x * (3. - 2. * x),
         AccelerationProfile::SmootherStep => |x| x * x * x * (x * (6. * x - 15.) + 10.),
@@ -31,3 +38,213 @@ pub fn smooth_profile(value: f32, profile: &AccelerationProfile) -> Result<f32,
     };
     Ok(value.signum() * smoothing_function(value.abs()))
 }
+
+/// Applies the profile to a stick position, see `CurveMode`.
+pub fn smooth_vector(
+    vector: [f32; 2],
+    profile: &AccelerationProfile,
+    mode: &CurveMode,
+) -> Result<[f32; 2], &'static str> {
+    let [x, y] = vector;
+    match mode {
+        CurveMode::PerAxis => Ok([smooth_profile(x, profile)?, smooth_profile(y, profile)?]),
+        CurveMode::Magnitude => {
+            let magnitude = x.hypot(y);
+            if magnitude == 0.0 {
+                return Ok([0.0, 0.0]);
+            }
+            // Profiles are defined up to 1, square-ish gates can go a bit past it in the corners.
+            let scale = smooth_profile(magnitude.min(1.0), profile)? / magnitude;
+            Ok([x * scale, y * scale])
+        }
+    }
+}
+
+impl AccelerationProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Power(exponent) if exponent.is_nan() || *exponent <= 0.0 => {
+                Err(format!("Power exponent must be positive, got {}", exponent))
+            }
+            Self::Custom(curve) => curve.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl CustomCurve {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::PiecewiseLinear(points) => {
+                if points.first() != Some(&[0.0, 0.0]) || points.last() != Some(&[1.0, 1.0]) {
+                    return Err("Points must start at [0, 0] and end at [1, 1]".to_string());
+                }
+                // Also catches NaN, which the comparisons below would let through.
+                if let Some(point) = points.iter().flatten().find(|v| !(0.0..=1.0).contains(*v)) {
+                    return Err(format!("Points must stay between 0 and 1, got {}", point));
+                }
+                for pair in points.windows(2) {
+                    let ([x0, y0], [x1, y1]) = (pair[0], pair[1]);
+                    if x1 <= x0 {
+                        return Err(format!("Inputs must increase, {} comes after {}", x1, x0));
+                    }
+                    if y1 < y0 {
+                        return Err(format!(
+                            "Outputs must not decrease, {} comes after {}",
+                            y1, y0
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            Self::Bezier(p1, p2) => {
+                // With every control coordinate in [0, 1] both x(t) and y(t) are monotonic,
+                // so the curve is a function and never goes down.
+                let in_range = |v: f32| (0.0..=1.0).contains(&v);
+                if p1.iter().chain(p2).all(|v| in_range(*v)) {
+                    Ok(())
+                } else {
+                    Err("Bezier control points must stay between 0 and 1".to_string())
+                }
+            }
+        }
+    }
+
+    /// Output of the curve for an input between 0 and 1.
+    pub fn evaluate(&self, x: f32) -> f32 {
+        match self {
+            Self::PiecewiseLinear(points) => {
+                // `validate` rejects those, but a straight line beats a panic.
+                if points.len() < 2 {
+                    return x;
+                }
+                let end = points
+                    .partition_point(|[px, _]| *px < x)
+                    .clamp(1, points.len() - 1);
+                let ([x0, y0], [x1, y1]) = (points[end - 1], points[end]);
+                y0 + (y1 - y0) * ((x - x0) / (x1 - x0)).clamp(0.0, 1.0)
+            }
+            Self::Bezier([x1, y1], [x2, y2]) => {
+                let bezier = |t: f32, p1: f32, p2: f32| {
+                    let u = 1.0 - t;
+                    3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t
+                };
+                // x(t) is monotonic, a bisection finds the t matching our input.
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..24 {
+                    let mid = (low + high) / 2.0;
+                    if bezier(mid, *x1, *x2) < x {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                bezier((low + high) / 2.0, *y1, *y2)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piecewise(points: &[[f32; 2]]) -> CustomCurve {
+        CustomCurve::PiecewiseLinear(points.to_vec())
+    }
+
+    #[test]
+    fn piecewise_interpolates_between_points() {
+        let curve = piecewise(&[[0.0, 0.0], [0.5, 0.2], [1.0, 1.0]]);
+        assert!(curve.validate().is_ok());
+        assert_eq!(curve.evaluate(0.0), 0.0);
+        assert!((curve.evaluate(0.25) - 0.1).abs() < 1e-6);
+        assert!((curve.evaluate(0.5) - 0.2).abs() < 1e-6);
+        assert!((curve.evaluate(0.75) - 0.6).abs() < 1e-6);
+        assert_eq!(curve.evaluate(1.0), 1.0);
+    }
+
+    #[test]
+    fn piecewise_needs_both_endpoints() {
+        assert!(piecewise(&[]).validate().is_err());
+        assert!(piecewise(&[[0.0, 0.0]]).validate().is_err());
+        assert!(piecewise(&[[0.1, 0.0], [1.0, 1.0]]).validate().is_err());
+        assert!(piecewise(&[[0.0, 0.0], [1.0, 0.9]]).validate().is_err());
+        assert!(piecewise(&[[0.0, 0.0], [1.0, 1.0]]).validate().is_ok());
+    }
+
+    #[test]
+    fn piecewise_rejects_unsorted_inputs() {
+        let curve = piecewise(&[[0.0, 0.0], [0.6, 0.3], [0.4, 0.5], [1.0, 1.0]]);
+        assert!(curve.validate().is_err());
+        let repeated = piecewise(&[[0.0, 0.0], [0.5, 0.3], [0.5, 0.5], [1.0, 1.0]]);
+        assert!(repeated.validate().is_err());
+    }
+
+    #[test]
+    fn piecewise_rejects_decreasing_outputs() {
+        let curve = piecewise(&[[0.0, 0.0], [0.3, 0.5], [0.6, 0.4], [1.0, 1.0]]);
+        assert!(curve.validate().is_err());
+        let flat = piecewise(&[[0.0, 0.0], [0.3, 0.5], [0.6, 0.5], [1.0, 1.0]]);
+        assert!(flat.validate().is_ok());
+    }
+
+    #[test]
+    fn piecewise_rejects_points_out_of_range() {
+        assert!(
+            piecewise(&[[0.0, 0.0], [0.5, 1.5], [1.0, 1.0]])
+                .validate()
+                .is_err()
+        );
+        assert!(
+            piecewise(&[[0.0, 0.0], [0.5, f32::NAN], [1.0, 1.0]])
+                .validate()
+                .is_err()
+        );
+        assert!(
+            piecewise(&[[0.0, 0.0], [f32::NAN, 0.5], [1.0, 1.0]])
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn bezier_control_points_stay_in_range() {
+        assert!(
+            CustomCurve::Bezier([0.4, 0.0], [0.6, 1.0])
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            CustomCurve::Bezier([0.4, -0.1], [0.6, 1.0])
+                .validate()
+                .is_err()
+        );
+        assert!(
+            CustomCurve::Bezier([0.4, 0.0], [1.2, 1.0])
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn degenerate_curve_does_not_panic() {
+        assert_eq!(piecewise(&[]).evaluate(0.3), 0.3);
+        assert_eq!(piecewise(&[[0.0, 0.0]]).evaluate(0.3), 0.3);
+    }
+
+    #[test]
+    fn magnitude_mode_keeps_diagonals_diagonal() {
+        let profile = AccelerationProfile::SmootherStep;
+        let push = 0.6;
+        let side = push / 2f32.sqrt();
+        let [x, y] = smooth_vector([side, side], &profile, &CurveMode::Magnitude).unwrap();
+        let [straight, _] = smooth_vector([push, 0.0], &profile, &CurveMode::Magnitude).unwrap();
+        assert!((x - y).abs() < 1e-6, "45° came out as {:?}", [x, y]);
+        assert!((x.hypot(y) - straight).abs() < 1e-6);
+
+        // Per axis, the same push comes out slower than a straight one.
+        let [x, y] = smooth_vector([side, side], &profile, &CurveMode::PerAxis).unwrap();
+        assert!(x.hypot(y) < straight - 0.05);
+    }
+}
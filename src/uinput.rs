@@ -0,0 +1,198 @@
+use evdev::uinput::VirtualDevice;
+use evdev::{AttributeSet, EventType, InputEvent, KeyCode, RelativeAxisCode};
+use std::path::PathBuf;
+
+use crate::keys::Key;
+use crate::output::{MouseButton, OutputResult, OutputSink};
+
+/// Virtual pointer created through /dev/uinput.
+/// The kernel exposes it as a regular evdev node, so anything we emit can be read back
+/// with `evtest` (or any evdev reader) on the node shown in the status.
+pub struct VirtualPointer {
+    device: VirtualDevice,
+    node: PathBuf,
+}
+
+impl VirtualPointer {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut device = VirtualDevice::builder()
+            .map_err(|e| format!("Could not open /dev/uinput ({}), check its permissions", e))?
+            .name("TheStickening virtual pointer")
+            .with_keys(&AttributeSet::from_iter(
+                [KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT, KeyCode::BTN_MIDDLE]
+                    .into_iter()
+                    .chain(Key::ALL.iter().map(|key| key_code(*key))),
+            ))?
+            .with_relative_axes(&AttributeSet::from_iter([
+                RelativeAxisCode::REL_X,
+                RelativeAxisCode::REL_Y,
+                RelativeAxisCode::REL_WHEEL,
+                RelativeAxisCode::REL_HWHEEL,
+            ]))?
+            .build()?;
+
+        let node = device
+            .enumerate_dev_nodes_blocking()?
+            .next()
+            .ok_or("The virtual pointer got no device node")??;
+
+        Ok(Self { device, node })
+    }
+
+    fn emit_relative(&mut self, axes: [(RelativeAxisCode, i32); 2]) -> std::io::Result<()> {
+        let events: Vec<InputEvent> = axes
+            .iter()
+            .filter(|(_, value)| *value != 0)
+            .map(|(axis, value)| InputEvent::new(EventType::RELATIVE.0, axis.0, *value))
+            .collect();
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.device.emit(&events)
+    }
+
+    fn emit_key(&mut self, code: KeyCode, pressed: bool) -> std::io::Result<()> {
+        self.device
+            .emit(&[InputEvent::new(EventType::KEY.0, code.0, pressed as i32)])
+    }
+}
+
+impl OutputSink for VirtualPointer {
+    fn move_relative(&mut self, dx: i32, dy: i32) -> OutputResult {
+        self.emit_relative([(RelativeAxisCode::REL_X, dx), (RelativeAxisCode::REL_Y, dy)])?;
+        Ok(())
+    }
+
+    fn button(&mut self, btn: MouseButton, pressed: bool) -> OutputResult {
+        let code = match btn {
+            MouseButton::Left => KeyCode::BTN_LEFT,
+            MouseButton::Right => KeyCode::BTN_RIGHT,
+            MouseButton::Middle => KeyCode::BTN_MIDDLE,
+        };
+        self.emit_key(code, pressed)?;
+        Ok(())
+    }
+
+    fn wheel(&mut self, vertical: i32, horizontal: i32) -> OutputResult {
+        self.emit_relative([
+            (RelativeAxisCode::REL_WHEEL, vertical),
+            (RelativeAxisCode::REL_HWHEEL, horizontal),
+        ])?;
+        Ok(())
+    }
+
+    fn key(&mut self, key: Key, pressed: bool) -> OutputResult {
+        self.emit_key(key_code(key), pressed)?;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("uinput virtual pointer at {}", self.node.display())
+    }
+}
+
+/// Linux key codes follow the physical qwerty layout, not the alphabet.
+fn key_code(key: Key) -> KeyCode {
+    match key {
+        Key::A => KeyCode::KEY_A,
+        Key::B => KeyCode::KEY_B,
+        Key::C => KeyCode::KEY_C,
+        Key::D => KeyCode::KEY_D,
+        Key::E => KeyCode::KEY_E,
+        Key::F => KeyCode::KEY_F,
+        Key::G => KeyCode::KEY_G,
+        Key::H => KeyCode::KEY_H,
+        Key::I => KeyCode::KEY_I,
+        Key::J => KeyCode::KEY_J,
+        Key::K => KeyCode::KEY_K,
+        Key::L => KeyCode::KEY_L,
+        Key::M => KeyCode::KEY_M,
+        Key::N => KeyCode::KEY_N,
+        Key::O => KeyCode::KEY_O,
+        Key::P => KeyCode::KEY_P,
+        Key::Q => KeyCode::KEY_Q,
+        Key::R => KeyCode::KEY_R,
+        Key::S => KeyCode::KEY_S,
+        Key::T => KeyCode::KEY_T,
+        Key::U => KeyCode::KEY_U,
+        Key::V => KeyCode::KEY_V,
+        Key::W => KeyCode::KEY_W,
+        Key::X => KeyCode::KEY_X,
+        Key::Y => KeyCode::KEY_Y,
+        Key::Z => KeyCode::KEY_Z,
+        Key::Num0 => KeyCode::KEY_0,
+        Key::Num1 => KeyCode::KEY_1,
+        Key::Num2 => KeyCode::KEY_2,
+        Key::Num3 => KeyCode::KEY_3,
+        Key::Num4 => KeyCode::KEY_4,
+        Key::Num5 => KeyCode::KEY_5,
+        Key::Num6 => KeyCode::KEY_6,
+        Key::Num7 => KeyCode::KEY_7,
+        Key::Num8 => KeyCode::KEY_8,
+        Key::Num9 => KeyCode::KEY_9,
+        Key::F1 => KeyCode::KEY_F1,
+        Key::F2 => KeyCode::KEY_F2,
+        Key::F3 => KeyCode::KEY_F3,
+        Key::F4 => KeyCode::KEY_F4,
+        Key::F5 => KeyCode::KEY_F5,
+        Key::F6 => KeyCode::KEY_F6,
+        Key::F7 => KeyCode::KEY_F7,
+        Key::F8 => KeyCode::KEY_F8,
+        Key::F9 => KeyCode::KEY_F9,
+        Key::F10 => KeyCode::KEY_F10,
+        Key::F11 => KeyCode::KEY_F11,
+        Key::F12 => KeyCode::KEY_F12,
+        Key::Enter => KeyCode::KEY_ENTER,
+        Key::Escape => KeyCode::KEY_ESC,
+        Key::Tab => KeyCode::KEY_TAB,
+        Key::Space => KeyCode::KEY_SPACE,
+        Key::Backspace => KeyCode::KEY_BACKSPACE,
+        Key::Delete => KeyCode::KEY_DELETE,
+        Key::Insert => KeyCode::KEY_INSERT,
+        Key::Home => KeyCode::KEY_HOME,
+        Key::End => KeyCode::KEY_END,
+        Key::PageUp => KeyCode::KEY_PAGEUP,
+        Key::PageDown => KeyCode::KEY_PAGEDOWN,
+        Key::Left => KeyCode::KEY_LEFT,
+        Key::Right => KeyCode::KEY_RIGHT,
+        Key::Up => KeyCode::KEY_UP,
+        Key::Down => KeyCode::KEY_DOWN,
+        Key::Ctrl => KeyCode::KEY_LEFTCTRL,
+        Key::Shift => KeyCode::KEY_LEFTSHIFT,
+        Key::Alt => KeyCode::KEY_LEFTALT,
+        Key::Super => KeyCode::KEY_LEFTMETA,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::Device;
+
+    #[test]
+    #[ignore = "needs write access to /dev/uinput"]
+    fn events_reach_the_device_node() {
+        let mut pointer = VirtualPointer::new().unwrap();
+        let mut device = Device::open(&pointer.node).unwrap();
+
+        pointer.move_relative(3, -2).unwrap();
+        pointer.button(MouseButton::Left, true).unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            for event in device.fetch_events().unwrap() {
+                if event.event_type() != EventType::SYNCHRONIZATION {
+                    received.push((event.event_type(), event.code(), event.value()));
+                }
+            }
+        }
+        assert_eq!(
+            received,
+            [
+                (EventType::RELATIVE, RelativeAxisCode::REL_X.0, 3),
+                (EventType::RELATIVE, RelativeAxisCode::REL_Y.0, -2),
+                (EventType::KEY, KeyCode::BTN_LEFT.0, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, MOUSEEVENTF_HWHEEL, MOUSEEVENTF_LEFTDOWN,
+    MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE,
+    MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_WHEEL, VIRTUAL_KEY, VK_BACK,
+    VK_CONTROL, VK_DELETE, VK_DOWN, VK_END, VK_ESCAPE, VK_F1, VK_F2, VK_F3, VK_F4, VK_F5, VK_F6,
+    VK_F7, VK_F8, VK_F9, VK_F10, VK_F11, VK_F12, VK_HOME, VK_INSERT, VK_LEFT, VK_LWIN, VK_MENU,
+    VK_NEXT, VK_PRIOR, VK_RETURN, VK_RIGHT, VK_SHIFT, VK_SPACE, VK_TAB, VK_UP, keybd_event,
+    mouse_event,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::WHEEL_DELTA;
+
+use crate::keys::Key;
+use crate::output::{MouseButton, OutputResult, OutputSink};
+
+/// Sends everything through the legacy `mouse_event`/`keybd_event` calls.
+pub struct Win32Sink;
+
+impl OutputSink for Win32Sink {
+    fn move_relative(&mut self, dx: i32, dy: i32) -> OutputResult {
+        if dx != 0 || dy != 0 {
+            unsafe {
+                // the 0 is cause we dont use the wheel
+                mouse_event(MOUSEEVENTF_MOVE, dx, dy, 0, 0);
+            }
+        }
+        Ok(())
+    }
+
+    fn button(&mut self, btn: MouseButton, pressed: bool) -> OutputResult {
+        let [down, up] = match btn {
+            MouseButton::Left => [MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP],
+            MouseButton::Middle => [MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP],
+            MouseButton::Right => [MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP],
+        };
+        let action = if pressed { down } else { up };
+        unsafe {
+            mouse_event(action, 0, 0, 0, 0);
+        }
+        Ok(())
+    }
+
+    fn wheel(&mut self, vertical: i32, horizontal: i32) -> OutputResult {
+        // Windows counts the wheel in fractions of a notch.
+        let notch = WHEEL_DELTA as i32;
+        unsafe {
+            if vertical != 0 {
+                mouse_event(MOUSEEVENTF_WHEEL, 0, 0, vertical * notch, 0);
+            }
+            if horizontal != 0 {
+                mouse_event(MOUSEEVENTF_HWHEEL, 0, 0, horizontal * notch, 0);
+            }
+        }
+        Ok(())
+    }
+
+    fn key(&mut self, key: Key, pressed: bool) -> OutputResult {
+        let (vk, extended) = virtual_key(key);
+        let mut flags = if extended { KEYEVENTF_EXTENDEDKEY } else { 0 };
+        if !pressed {
+            flags |= KEYEVENTF_KEYUP;
+        }
+        unsafe {
+            keybd_event(vk as u8, 0, flags, 0);
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        String::from("Windows mouse_event and keybd_event")
+    }
+}
+
+/// Virtual key code, and whether it is an extended key. Without the extended flag the
+/// arrows and the navigation block come out as their numpad twins.
+fn virtual_key(key: Key) -> (VIRTUAL_KEY, bool) {
+    // Letters and digits are their ASCII code.
+    match key {
+        Key::A => (b'A' as VIRTUAL_KEY, false),
+        Key::B => (b'B' as VIRTUAL_KEY, false),
+        Key::C => (b'C' as VIRTUAL_KEY, false),
+        Key::D => (b'D' as VIRTUAL_KEY, false),
+        Key::E => (b'E' as VIRTUAL_KEY, false),
+        Key::F => (b'F' as VIRTUAL_KEY, false),
+        Key::G => (b'G' as VIRTUAL_KEY, false),
+        Key::H => (b'H' as VIRTUAL_KEY, false),
+        Key::I => (b'I' as VIRTUAL_KEY, false),
+        Key::J => (b'J' as VIRTUAL_KEY, false),
+        Key::K => (b'K' as VIRTUAL_KEY, false),
+        Key::L => (b'L' as VIRTUAL_KEY, false),
+        Key::M => (b'M' as VIRTUAL_KEY, false),
+        Key::N => (b'N' as VIRTUAL_KEY, false),
+        Key::O => (b'O' as VIRTUAL_KEY, false),
+        Key::P => (b'P' as VIRTUAL_KEY, false),
+        Key::Q => (b'Q' as VIRTUAL_KEY, false),
+        Key::R => (b'R' as VIRTUAL_KEY, false),
+        Key::S => (b'S' as VIRTUAL_KEY, false),
+        Key::T => (b'T' as VIRTUAL_KEY, false),
+        Key::U => (b'U' as VIRTUAL_KEY, false),
+        Key::V => (b'V' as VIRTUAL_KEY, false),
+        Key::W => (b'W' as VIRTUAL_KEY, false),
+        Key::X => (b'X' as VIRTUAL_KEY, false),
+        Key::Y => (b'Y' as VIRTUAL_KEY, false),
+        Key::Z => (b'Z' as VIRTUAL_KEY, false),
+        Key::Num0 => (b'0' as VIRTUAL_KEY, false),
+        Key::Num1 => (b'1' as VIRTUAL_KEY, false),
+        Key::Num2 => (b'2' as VIRTUAL_KEY, false),
+        Key::Num3 => (b'3' as VIRTUAL_KEY, false),
+        Key::Num4 => (b'4' as VIRTUAL_KEY, false),
+        Key::Num5 => (b'5' as VIRTUAL_KEY, false),
+        Key::Num6 => (b'6' as VIRTUAL_KEY, false),
+        Key::Num7 => (b'7' as VIRTUAL_KEY, false),
+        Key::Num8 => (b'8' as VIRTUAL_KEY, false),
+        Key::Num9 => (b'9' as VIRTUAL_KEY, false),
+        Key::F1 => (VK_F1, false),
+        Key::F2 => (VK_F2, false),
+        Key::F3 => (VK_F3, false),
+        Key::F4 => (VK_F4, false),
+        Key::F5 => (VK_F5, false),
+        Key::F6 => (VK_F6, false),
+        Key::F7 => (VK_F7, false),
+        Key::F8 => (VK_F8, false),
+        Key::F9 => (VK_F9, false),
+        Key::F10 => (VK_F10, false),
+        Key::F11 => (VK_F11, false),
+        Key::F12 => (VK_F12, false),
+        Key::Enter => (VK_RETURN, false),
+        Key::Escape => (VK_ESCAPE, false),
+        Key::Tab => (VK_TAB, false),
+        Key::Space => (VK_SPACE, false),
+        Key::Backspace => (VK_BACK, false),
+        Key::Delete => (VK_DELETE, true),
+        Key::Insert => (VK_INSERT, true),
+        Key::Home => (VK_HOME, true),
+        Key::End => (VK_END, true),
+        Key::PageUp => (VK_PRIOR, true),
+        Key::PageDown => (VK_NEXT, true),
+        Key::Left => (VK_LEFT, true),
+        Key::Right => (VK_RIGHT, true),
+        Key::Up => (VK_UP, true),
+        Key::Down => (VK_DOWN, true),
+        Key::Ctrl => (VK_CONTROL, false),
+        Key::Shift => (VK_SHIFT, false),
+        Key::Alt => (VK_MENU, false),
+        Key::Super => (VK_LWIN, true),
+    }
+}
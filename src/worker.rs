@@ -0,0 +1,246 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, MAX_FREQUENCY};
+use crate::gamepad::GamepadHandler;
+
+/// How often the worker reports its status to the GUI.
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// We sleep until that close to the next tick, then spin. OS sleeps are not precise enough
+/// for 1000 Hz, especially on Windows where even with a 1 ms timer they often oversleep.
+#[cfg(target_os = "windows")]
+const SPIN_MARGIN: Duration = Duration::from_micros(1500);
+#[cfg(not(target_os = "windows"))]
+const SPIN_MARGIN: Duration = Duration::from_micros(200);
+
+/// Messages from the GUI to the gamepad thread
+#[derive(Debug)]
+pub enum WorkerCommand {
+    UpdateConfig(Box<Config>),
+    Stop,
+}
+
+/// Health of the gamepad thread, sent to the GUI every `STATUS_INTERVAL`.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerStatus {
+    /// Ticks per second actually achieved
+    pub tick_rate: f32,
+    /// How late the ticks were compared to their schedule, on average and at worst.
+    pub mean_jitter: Duration,
+    pub max_jitter: Duration,
+    pub last_error: Option<String>,
+    /// Where the events go, see `OutputSink::describe`.
+    pub output: String,
+}
+
+/// Runs the `GamepadHandler` loop on its own thread so the GUI can never delay the cursor.
+pub struct GamepadWorker {
+    commands: Sender<WorkerCommand>,
+    status: Receiver<WorkerStatus>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl GamepadWorker {
+    pub fn spawn(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let (command_sender, command_receiver) = mpsc::channel();
+        let (status_sender, status_receiver) = mpsc::channel();
+        // Gilrs and the output backends are not Send, so the handler is built on the thread
+        // itself and we wait to know whether that worked.
+        let (ready_sender, ready_receiver) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name("gamepad".to_string())
+            .spawn(move || {
+                let handler = match GamepadHandler::new(config) {
+                    Ok(handler) => handler,
+                    Err(e) => {
+                        let _ = ready_sender.send(Err(e.to_string()));
+                        return;
+                    }
+                };
+                let _ = ready_sender.send(Ok(()));
+                run(handler, command_receiver, status_sender);
+            })?;
+
+        ready_receiver
+            .recv()
+            .map_err(|_| "Gamepad thread died during startup")??;
+
+        Ok(Self {
+            commands: command_sender,
+            status: status_receiver,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn update_config(&self, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+        self.commands
+            .send(WorkerCommand::UpdateConfig(Box::new(config)))
+            .map_err(|_| "Gamepad thread is not running")?;
+        Ok(())
+    }
+
+    /// Latest status reported by the thread, if a new one arrived since the last call.
+    pub fn latest_status(&self) -> Option<WorkerStatus> {
+        self.status.try_iter().last()
+    }
+}
+
+impl Drop for GamepadWorker {
+    fn drop(&mut self) {
+        let _ = self.commands.send(WorkerCommand::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn tick_period(frequency: f32) -> Duration {
+    Duration::from_secs_f32(1.0 / frequency.clamp(1., MAX_FREQUENCY))
+}
+
+fn run(
+    mut handler: GamepadHandler,
+    commands: Receiver<WorkerCommand>,
+    status: Sender<WorkerStatus>,
+) {
+    let _timer_resolution = raise_thread_priority();
+
+    let mut period = tick_period(handler.config.frequency);
+    let mut last_tick = Instant::now();
+    let mut next_tick = last_tick + period;
+    let mut stats = TickStats::new(last_tick);
+    let mut last_error = None;
+    // Printed along with the status, a broken device would fail every tick.
+    let mut frame_errors = 0;
+
+    loop {
+        loop {
+            match commands.try_recv() {
+                Ok(WorkerCommand::UpdateConfig(config)) => {
+                    if let Err(e) = handler.set_config(*config) {
+                        last_error = Some(e.to_string());
+                    }
+                    period = tick_period(handler.config.frequency);
+                }
+                Ok(WorkerCommand::Stop) | Err(TryRecvError::Disconnected) => return,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        sleep_until(next_tick);
+        let now = Instant::now();
+        stats.record(now.saturating_duration_since(next_tick));
+
+        if let Err(e) = handler.process_frame(now - last_tick) {
+            frame_errors += 1;
+            last_error = Some(e.to_string());
+        }
+        last_tick = now;
+
+        if now - stats.since >= STATUS_INTERVAL {
+            if frame_errors > 0 {
+                if let Some(e) = &last_error {
+                    eprintln!("Error processing gamepad ({} times): {}", frame_errors, e);
+                }
+                frame_errors = 0;
+            }
+            let report = stats.report(now, last_error.take(), handler.output_description());
+            if status.send(report).is_err() {
+                return;
+            }
+            stats = TickStats::new(now);
+        }
+
+        next_tick += period;
+        if next_tick < now {
+            // We fell behind by more than a tick, no point trying to catch up.
+            next_tick = now + period;
+        }
+    }
+}
+
+fn sleep_until(deadline: Instant) {
+    let now = Instant::now();
+    if deadline > now + SPIN_MARGIN {
+        thread::sleep(deadline - now - SPIN_MARGIN);
+    }
+    while Instant::now() < deadline {
+        thread::yield_now();
+    }
+}
+
+struct TickStats {
+    since: Instant,
+    ticks: u32,
+    total_lateness: Duration,
+    max_lateness: Duration,
+}
+
+impl TickStats {
+    fn new(since: Instant) -> Self {
+        Self {
+            since,
+            ticks: 0,
+            total_lateness: Duration::ZERO,
+            max_lateness: Duration::ZERO,
+        }
+    }
+
+    fn record(&mut self, lateness: Duration) {
+        self.ticks += 1;
+        self.total_lateness += lateness;
+        self.max_lateness = self.max_lateness.max(lateness);
+    }
+
+    fn report(&self, now: Instant, last_error: Option<String>, output: String) -> WorkerStatus {
+        WorkerStatus {
+            tick_rate: self.ticks as f32 / (now - self.since).as_secs_f32(),
+            mean_jitter: self.total_lateness / self.ticks.max(1),
+            max_jitter: self.max_lateness,
+            last_error,
+            output,
+        }
+    }
+}
+
+/// Gives the timer resolution back once dropped, `timeBeginPeriod` is system wide and costs
+/// power until someone ends it.
+struct TimerResolution;
+
+impl Drop for TimerResolution {
+    fn drop(&mut self) {
+        #[cfg(target_os = "windows")]
+        unsafe {
+            windows_sys::Win32::Media::timeEndPeriod(1);
+        }
+    }
+}
+
+/// Best effort, we still run at normal priority if the OS says no.
+/// Keep the returned value for as long as the thread runs.
+fn raise_thread_priority() -> TimerResolution {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows_sys::Win32::Media::timeBeginPeriod;
+        use windows_sys::Win32::System::Threading::{
+            GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_HIGHEST,
+        };
+        // Default timer resolution is 15.6 ms, way too coarse for our sleeps.
+        timeBeginPeriod(1);
+        SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_HIGHEST);
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe {
+        // On Linux each thread has its own nice value, 0 targets the calling thread.
+        // Going below 0 needs CAP_SYS_NICE, without it this fails and we keep the default.
+        if libc::setpriority(libc::PRIO_PROCESS, 0, -10) != 0 {
+            println!("Could not raise the gamepad thread priority, running at normal priority");
+        }
+    }
+
+    TimerResolution
+}
@@ -0,0 +1,236 @@
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::{
+    BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, ConnectionExt as _, KEY_PRESS_EVENT,
+    KEY_RELEASE_EVENT, Keycode, Keysym, MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+use crate::keys::Key;
+use crate::output::{MouseButton, OutputResult, OutputSink};
+
+// X11 core pointer buttons, the wheel is reported as buttons too.
+const BUTTON_LEFT: u8 = 1;
+const BUTTON_MIDDLE: u8 = 2;
+const BUTTON_RIGHT: u8 = 3;
+const WHEEL_UP: u8 = 4;
+const WHEEL_DOWN: u8 = 5;
+const WHEEL_LEFT: u8 = 6;
+const WHEEL_RIGHT: u8 = 7;
+
+/// Injects events into the X server through the XTest extension.
+/// Works wherever $DISPLAY points to, Xvfb included, no access to /dev/uinput needed.
+pub struct XTestSink {
+    conn: RustConnection,
+    min_keycode: Keycode,
+    keysyms_per_keycode: usize,
+    keysyms: Vec<Keysym>,
+    /// $DISPLAY when we connected
+    display: String,
+}
+
+impl XTestSink {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let (conn, _screen) = x11rb::connect(None)?;
+        if conn
+            .extension_information(xtest::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Err("The X server does not support the XTest extension".into());
+        }
+
+        // We resolve keysyms ourselves so we do not depend on xkbcommon.
+        let min_keycode = conn.setup().min_keycode;
+        let count = conn.setup().max_keycode - min_keycode + 1;
+        let mapping = conn.get_keyboard_mapping(min_keycode, count)?.reply()?;
+
+        Ok(Self {
+            conn,
+            min_keycode,
+            keysyms_per_keycode: mapping.keysyms_per_keycode as usize,
+            keysyms: mapping.keysyms,
+            display: std::env::var("DISPLAY").unwrap_or_default(),
+        })
+    }
+
+    fn keycode(&self, keysym: Keysym) -> Option<Keycode> {
+        self.keysyms
+            .chunks(self.keysyms_per_keycode.max(1))
+            .position(|syms| syms.contains(&keysym))
+            .map(|index| self.min_keycode + index as Keycode)
+    }
+
+    /// Queues the request, it goes out with the rest of the frame in `flush`. Waiting for each
+    /// one to be acknowledged would cost a round trip to the server per event.
+    fn fake_input(&self, type_: u8, detail: u8, x: i16, y: i16) -> OutputResult {
+        // Root 0 means the screen the pointer is currently on.
+        self.conn
+            .xtest_fake_input(type_, detail, x11rb::CURRENT_TIME, 0, x, y, 0)?;
+        Ok(())
+    }
+
+    fn click(&self, button: u8, times: i32) -> OutputResult {
+        for _ in 0..times {
+            self.fake_input(BUTTON_PRESS_EVENT, button, 0, 0)?;
+            self.fake_input(BUTTON_RELEASE_EVENT, button, 0, 0)?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for XTestSink {
+    fn move_relative(&mut self, dx: i32, dy: i32) -> OutputResult {
+        if dx == 0 && dy == 0 {
+            return Ok(());
+        }
+        // A detail of 1 makes the motion relative to the current position.
+        let clamp = |v: i32| v.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        self.fake_input(MOTION_NOTIFY_EVENT, 1, clamp(dx), clamp(dy))
+    }
+
+    fn button(&mut self, btn: MouseButton, pressed: bool) -> OutputResult {
+        let button = match btn {
+            MouseButton::Left => BUTTON_LEFT,
+            MouseButton::Middle => BUTTON_MIDDLE,
+            MouseButton::Right => BUTTON_RIGHT,
+        };
+        let type_ = if pressed {
+            BUTTON_PRESS_EVENT
+        } else {
+            BUTTON_RELEASE_EVENT
+        };
+        self.fake_input(type_, button, 0, 0)
+    }
+
+    fn wheel(&mut self, vertical: i32, horizontal: i32) -> OutputResult {
+        let up_or_down = if vertical > 0 { WHEEL_UP } else { WHEEL_DOWN };
+        self.click(up_or_down, vertical.abs())?;
+        let left_or_right = if horizontal > 0 {
+            WHEEL_RIGHT
+        } else {
+            WHEEL_LEFT
+        };
+        self.click(left_or_right, horizontal.abs())
+    }
+
+    fn key(&mut self, key: Key, pressed: bool) -> OutputResult {
+        let keysym = keysym(key);
+        let keycode = self
+            .keycode(keysym)
+            .ok_or_else(|| format!("No keycode produces {:?} on this keyboard layout", key))?;
+        let type_ = if pressed {
+            KEY_PRESS_EVENT
+        } else {
+            KEY_RELEASE_EVENT
+        };
+        self.fake_input(type_, keycode, 0, 0)
+    }
+
+    fn describe(&self) -> String {
+        format!("XTest on display {}", self.display)
+    }
+
+    fn flush(&mut self) -> OutputResult {
+        self.conn.flush()?;
+        // We do not select any event, whatever shows up is an error about an earlier request.
+        while let Some(event) = self.conn.poll_for_event()? {
+            if let Event::Error(error) = event {
+                return Err(format!("XTest request failed: {:?}", error.error_kind).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keysyms from X11/keysymdef.h, letters and digits are their latin-1 code.
+fn keysym(key: Key) -> Keysym {
+    match key {
+        Key::A => 0x0061,
+        Key::B => 0x0062,
+        Key::C => 0x0063,
+        Key::D => 0x0064,
+        Key::E => 0x0065,
+        Key::F => 0x0066,
+        Key::G => 0x0067,
+        Key::H => 0x0068,
+        Key::I => 0x0069,
+        Key::J => 0x006a,
+        Key::K => 0x006b,
+        Key::L => 0x006c,
+        Key::M => 0x006d,
+        Key::N => 0x006e,
+        Key::O => 0x006f,
+        Key::P => 0x0070,
+        Key::Q => 0x0071,
+        Key::R => 0x0072,
+        Key::S => 0x0073,
+        Key::T => 0x0074,
+        Key::U => 0x0075,
+        Key::V => 0x0076,
+        Key::W => 0x0077,
+        Key::X => 0x0078,
+        Key::Y => 0x0079,
+        Key::Z => 0x007a,
+        Key::Num0 => 0x0030,
+        Key::Num1 => 0x0031,
+        Key::Num2 => 0x0032,
+        Key::Num3 => 0x0033,
+        Key::Num4 => 0x0034,
+        Key::Num5 => 0x0035,
+        Key::Num6 => 0x0036,
+        Key::Num7 => 0x0037,
+        Key::Num8 => 0x0038,
+        Key::Num9 => 0x0039,
+        Key::F1 => 0xffbe,
+        Key::F2 => 0xffbf,
+        Key::F3 => 0xffc0,
+        Key::F4 => 0xffc1,
+        Key::F5 => 0xffc2,
+        Key::F6 => 0xffc3,
+        Key::F7 => 0xffc4,
+        Key::F8 => 0xffc5,
+        Key::F9 => 0xffc6,
+        Key::F10 => 0xffc7,
+        Key::F11 => 0xffc8,
+        Key::F12 => 0xffc9,
+        Key::Enter => 0xff0d,
+        Key::Escape => 0xff1b,
+        Key::Tab => 0xff09,
+        Key::Space => 0x0020,
+        Key::Backspace => 0xff08,
+        Key::Delete => 0xffff,
+        Key::Insert => 0xff63,
+        Key::Home => 0xff50,
+        Key::End => 0xff57,
+        Key::PageUp => 0xff55,
+        Key::PageDown => 0xff56,
+        Key::Left => 0xff51,
+        Key::Up => 0xff52,
+        Key::Right => 0xff53,
+        Key::Down => 0xff54,
+        Key::Ctrl => 0xffe3,
+        Key::Shift => 0xffe1,
+        Key::Alt => 0xffe9,
+        Key::Super => 0xffeb,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs an X server with XTest on $DISPLAY, for instance Xvfb"]
+    fn move_relative_moves_the_pointer() {
+        let mut sink = XTestSink::new().unwrap();
+        let root = sink.conn.setup().roots[0].root;
+        // A detail of 0 moves to absolute coordinates, so we know where we start from.
+        sink.fake_input(MOTION_NOTIFY_EVENT, 0, 100, 100).unwrap();
+        sink.move_relative(10, -5).unwrap();
+        sink.flush().unwrap();
+
+        let pointer = sink.conn.query_pointer(root).unwrap().reply().unwrap();
+        assert_eq!((pointer.root_x, pointer.root_y), (110, 95));
+    }
+}